@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use crate::game::{Game, GameResult, Player};
+use crate::mcts::{Mcts, MctsConfig};
+
+/// Index of a configuration taking part in a tournament
+pub type ConfigId = usize;
+
+pub const INITIAL_RATING: f64 = 1500.0;
+const ELO_K: f64 = 16.0;
+const ELO_MAX_PASSES: usize = 1000;
+const ELO_TOLERANCE: f64 = 0.01;
+
+/// Result of one game between two configurations
+#[derive(Clone, Copy, Debug)]
+pub struct MatchOutcome {
+    /// Configuration playing X, who moves first
+    pub first: ConfigId,
+    /// Configuration playing O
+    pub second: ConfigId,
+    pub result: GameResult,
+}
+
+impl MatchOutcome {
+    /// Score of the first configuration: 1 for a win, 0.5 for a draw, 0 for a loss
+    fn first_score(&self) -> f64 {
+        match self.result {
+            GameResult::Win(player) => f64::from(player == Player::X),
+            GameResult::Draw | GameResult::End(_) => 0.5,
+        }
+    }
+}
+
+/// Play one game between agents with the `first` (X) and `second` (O) configurations
+pub fn play_match<G: Game>(mut game: G, first: &MctsConfig, second: &MctsConfig) -> GameResult {
+    let mut agent_x = Mcts::with_config(first.clone());
+    let mut agent_o = Mcts::with_config(second.clone());
+    loop {
+        if let Some(result) = game.result_with_limit() {
+            return result;
+        }
+        let agent = match game.current_player() {
+            Player::X => &mut agent_x,
+            Player::O => &mut agent_o,
+        };
+        let action = agent
+            .search(&game)
+            .expect("No action for an unfinished game");
+        game.step(action).unwrap();
+    }
+}
+
+/// Who moves first in each game between two configurations
+#[derive(Clone, Copy, Debug)]
+pub enum FirstPlayer {
+    /// Each configuration in turn, so both start equally often over an even number of games
+    Alternate,
+    /// A configuration drawn at random, from this seed
+    Random(u64),
+}
+
+/// Play every pair of configurations against each other `games` times,
+/// picking who moves first with `first_player`
+pub fn round_robin<G: Game>(
+    game: &G,
+    configs: &[MctsConfig],
+    games: usize,
+    first_player: FirstPlayer,
+) -> Vec<MatchOutcome> {
+    let mut rng = match first_player {
+        FirstPlayer::Alternate => None,
+        FirstPlayer::Random(seed) => Some(fastrand::Rng::with_seed(seed)),
+    };
+    let mut outcomes = vec![];
+    for a in 0..configs.len() {
+        for b in a + 1..configs.len() {
+            for i in 0..games {
+                let a_first = rng.as_mut().map_or(i % 2 == 0, fastrand::Rng::bool);
+                let (first, second) = if a_first { (a, b) } else { (b, a) };
+                let result = play_match(game.clone(), &configs[first], &configs[second]);
+                outcomes.push(MatchOutcome {
+                    first,
+                    second,
+                    result,
+                });
+            }
+        }
+    }
+    outcomes
+}
+
+/// Games and wins of a configuration when moving first or second
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SideStats {
+    pub first_games: usize,
+    pub first_wins: usize,
+    pub second_games: usize,
+    pub second_wins: usize,
+}
+
+impl SideStats {
+    /// Share of the games moving first that were won
+    pub fn first_win_rate(&self) -> f64 {
+        win_rate(self.first_wins, self.first_games)
+    }
+
+    /// Share of the games moving second that were won
+    pub fn second_win_rate(&self) -> f64 {
+        win_rate(self.second_wins, self.second_games)
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn win_rate(wins: usize, games: usize) -> f64 {
+    if games == 0 {
+        0.0
+    } else {
+        wins as f64 / games as f64
+    }
+}
+
+/// Results of each configuration split by the side it played
+pub fn side_stats(results: &[MatchOutcome]) -> HashMap<ConfigId, SideStats> {
+    let mut stats: HashMap<ConfigId, SideStats> = HashMap::new();
+    for outcome in results {
+        let first = stats.entry(outcome.first).or_default();
+        first.first_games += 1;
+        first.first_wins += usize::from(outcome.result == GameResult::Win(Player::X));
+        let second = stats.entry(outcome.second).or_default();
+        second.second_games += 1;
+        second.second_wins += usize::from(outcome.result == GameResult::Win(Player::O));
+    }
+    stats
+}
+
+/// Elo ratings fitted to the outcomes, starting from 1500.
+/// Ratings move with the mean score surprise of each configuration until they settle.
+pub fn compute_elo(results: &[MatchOutcome]) -> HashMap<ConfigId, f64> {
+    let mut ratings: HashMap<ConfigId, f64> = results
+        .iter()
+        .flat_map(|outcome| [outcome.first, outcome.second])
+        .map(|id| (id, INITIAL_RATING))
+        .collect();
+
+    for _ in 0..ELO_MAX_PASSES {
+        let mut surprise: HashMap<ConfigId, (f64, f64)> = HashMap::new();
+        for outcome in results {
+            let expected = 1.0
+                / (1.0 + 10f64.powf((ratings[&outcome.second] - ratings[&outcome.first]) / 400.0));
+            let delta = outcome.first_score() - expected;
+            for (id, delta) in [(outcome.first, delta), (outcome.second, -delta)] {
+                let (sum, games) = surprise.entry(id).or_default();
+                *sum += delta;
+                *games += 1.0;
+            }
+        }
+
+        let mut max_update: f64 = 0.0;
+        for (id, (sum, games)) in surprise {
+            let update = ELO_K * sum / games;
+            *ratings.get_mut(&id).unwrap() += update;
+            max_update = max_update.max(update.abs());
+        }
+        if max_update < ELO_TOLERANCE {
+            break;
+        }
+    }
+    ratings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tictactoe::TicTacToe;
+
+    fn outcome(first: ConfigId, second: ConfigId, result: GameResult) -> MatchOutcome {
+        MatchOutcome {
+            first,
+            second,
+            result,
+        }
+    }
+
+    #[test]
+    fn winners_rate_higher() {
+        let results = [
+            outcome(0, 1, GameResult::Win(Player::X)),
+            outcome(1, 0, GameResult::Win(Player::O)),
+            outcome(0, 1, GameResult::Draw),
+            outcome(1, 0, GameResult::Win(Player::X)),
+            outcome(0, 1, GameResult::Win(Player::X)),
+        ];
+        let ratings = compute_elo(&results);
+        assert!(ratings[&0] > ratings[&1]);
+        assert!((ratings[&0] + ratings[&1] - 2.0 * INITIAL_RATING).abs() < 1e-6);
+    }
+
+    #[test]
+    fn side_stats_split_games_by_side() {
+        let results = [
+            outcome(0, 1, GameResult::Win(Player::X)),
+            outcome(1, 0, GameResult::Win(Player::O)),
+            outcome(1, 0, GameResult::Draw),
+        ];
+        let stats = side_stats(&results);
+        assert_eq!((stats[&0].first_games, stats[&0].first_wins), (1, 1));
+        assert_eq!((stats[&0].second_games, stats[&0].second_wins), (2, 1));
+        assert!((stats[&1].first_win_rate()).abs() < 1e-9);
+        assert!((stats[&1].second_win_rate()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn alternating_round_robins_swap_who_starts() {
+        let config = MctsConfig {
+            iters: 20,
+            seed: Some(1),
+            ..MctsConfig::default()
+        };
+        let configs = [config.clone(), config];
+        let outcomes = round_robin(&TicTacToe::default(), &configs, 4, FirstPlayer::Alternate);
+        let firsts: Vec<ConfigId> = outcomes.iter().map(|outcome| outcome.first).collect();
+        assert_eq!(firsts, [0, 1, 0, 1]);
+    }
+}
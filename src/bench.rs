@@ -0,0 +1,47 @@
+use std::time::Instant;
+
+use crate::game::{Game, connect4::Connect4};
+use crate::mcts::{Mcts, MctsConfig};
+
+const BENCH_ITERS: u32 = 10_000;
+const BENCH_SEED: u64 = 42;
+const BENCH_MOVES: [usize; 8] = [3, 3, 2, 4, 2, 2, 4, 1];
+
+/// Run a fixed-seed search on a mid-game Connect4 position and return iterations per second
+pub fn bench_search() -> f64 {
+    let mut game = Connect4::default();
+    for action in BENCH_MOVES {
+        game.step(action).unwrap();
+    }
+
+    let mut agent = Mcts::with_config(MctsConfig {
+        iters: BENCH_ITERS,
+        seed: Some(BENCH_SEED),
+        ..MctsConfig::default()
+    });
+
+    let start = Instant::now();
+    agent.search(&game);
+    f64::from(BENCH_ITERS) / start.elapsed().as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bench_position_is_playable() {
+        let mut game = Connect4::default();
+        for action in BENCH_MOVES {
+            game.step(action).unwrap();
+        }
+        assert_eq!(game.result(), None);
+        assert_eq!(game.allowed_actions().len(), 7);
+    }
+
+    #[test]
+    fn bench_reports_a_positive_rate() {
+        let rate = bench_search();
+        assert!(rate.is_finite() && rate > 0.0, "rate {rate}");
+    }
+}
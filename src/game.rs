@@ -2,6 +2,7 @@ pub mod connect4;
 pub mod tetris;
 pub mod tictactoe;
 
+use std::any::Any;
 use std::fmt::{self, Debug};
 
 pub type Action = usize;
@@ -9,13 +10,274 @@ pub type Action = usize;
 pub trait Game: Debug + Clone {
     fn print_instructions(&self);
     fn result(&self) -> Option<GameResult>;
-    fn current_reward(&self) -> f64;
+
+    /// Reward accumulated so far, such as the score of a single-player game.
+    /// Games decided only by their result keep the default of 0.
+    fn current_reward(&self) -> f64 {
+        0.0
+    }
+
     fn allowed_actions(&self) -> Vec<Action>;
     fn current_player(&self) -> Player;
+
+    /// Human-readable name of an action
+    fn action_name(&self, action: Action) -> String {
+        action.to_string()
+    }
+
+    /// Parse an action from its name, as given by `action_name`
+    fn action_from_name(&self, name: &str) -> Result<Action, &'static str> {
+        name.trim().parse().map_err(|_| "Unknown action")
+    }
+
+    /// Number of players taking turns, 1 for puzzles and score attack games.
+    fn num_players(&self) -> usize {
+        2
+    }
+
+    /// Player to move after the current player's move.
+    /// Games with extra turns or a single player override this.
+    fn next_player(&self) -> Player {
+        self.current_player().opponent()
+    }
+
+    /// Sign turning a value for the player to move in `next`, a position reached from this
+    /// one, into a value for the player to move here: 1 when the same player moves again,
+    /// as after an extra turn, and -1 when the turn passed to the opponent
+    fn turn_parity(&self, next: &Self) -> i32 {
+        if next.current_player() == self.current_player() {
+            1
+        } else {
+            -1
+        }
+    }
+
     fn step(&mut self, action: Action) -> Result<(), &'static str>;
+
+    /// Apply `action` and return the reward it earned, 0 in games without rewards
+    fn step_returning_reward(&mut self, action: Action) -> Result<f64, &'static str> {
+        let before = self.current_reward();
+        self.step(action)?;
+        Ok(self.current_reward() - before)
+    }
+
+    /// Number of moves played so far
+    fn ply(&self) -> usize;
+    /// Action applied by the last `step`, if any
+    fn last_action(&self) -> Option<Action>;
+
+    /// Number of pieces on the board of each player, X first
+    fn material(&self) -> Vec<usize>;
+
+    /// Check the invariants of the state, e.g. after loading it or building it by hand
+    fn validate(&self) -> Result<(), &'static str>;
+
+    /// Compact key identifying the position, readable in logs and usable as a map key
+    fn state_key(&self) -> String;
+
+    /// Number of moves after which the game is declared a draw
+    fn ply_limit(&self) -> Option<usize> {
+        None
+    }
+
+    /// Fixed-shape encoding of the state for neural-net input.
+    /// Returns a flat buffer in row-major order and its shape, planes first.
+    fn to_tensor(&self) -> (Vec<f32>, Vec<usize>);
+
+    /// Number of actions, allowed or not. Actions are in `0..action_space_size()`.
+    fn action_space_size(&self) -> usize;
+
+    /// Number of legal actions in this position, e.g. to scale search parameters to the game.
+    /// `action_space_size` is the most it can be.
+    #[allow(dead_code)]
+    fn branching_factor(&self) -> usize {
+        self.allowed_actions().len()
+    }
+
+    /// Whether each action of the action space is allowed, e.g. to mask policy logits
+    fn legal_action_mask(&self) -> Vec<bool> {
+        let mut mask = vec![false; self.action_space_size()];
+        for action in self.allowed_actions() {
+            mask[action] = true;
+        }
+        mask
+    }
+
+    /// A copy of the game consistent with what `observer` can see, with hidden state
+    /// re-sampled from the search RNG. Games without hidden information return a plain clone.
+    fn observable_clone(&self, _observer: Player, _rng: &mut fastrand::Rng) -> Self {
+        self.clone()
+    }
+
+    /// Board cells, as indices, whose lines an action can change, so result checks can
+    /// stay local. `None` means the whole board.
+    fn affected_region(&self, _action: Action) -> Option<Vec<usize>> {
+        None
+    }
+
+    /// Symmetries of the current position, as permutations of the action space.
+    /// Symmetric actions lead to equivalent positions.
+    fn symmetries(&self) -> Vec<Vec<Action>> {
+        vec![]
+    }
+
+    /// Result of the game after playing `action`, without changing the state.
+    /// `None` if the game goes on or the action isn't allowed.
+    fn result_after(&self, action: Action) -> Option<GameResult> {
+        let mut game = self.clone();
+        game.step(action).ok()?;
+        game.result()
+    }
+
+    /// Each legal action with the state it leads to, skipping actions `step` rejects
+    fn successors(&self) -> Vec<(Action, Self)> {
+        self.allowed_actions()
+            .into_iter()
+            .filter_map(|action| {
+                let mut next = self.clone();
+                match next.step(action) {
+                    Ok(()) => Some((action, next)),
+                    Err(e) => {
+                        log::warn!("Skipping action {action} rejected by the game: {e}");
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Whether `action` wins the game for the player making it
+    fn is_winning_action(&self, action: Action) -> bool {
+        let player = self.current_player();
+        self.result_after(action) == Some(GameResult::Win(player))
+    }
+
+    /// Known best move for the position from an opening book, to play without searching
+    fn book_move(&self) -> Option<Action> {
+        None
+    }
+
+    /// A good move found by a cheap rule, for rollouts to follow
+    fn heuristic_move(&self) -> Option<Action> {
+        None
+    }
+
+    /// Move played by rollouts when they don't follow the heuristic, drawn from the search
+    /// RNG so seeded searches are reproducible. Uniformly random by default.
+    fn rollout_action(&self, rng: &mut fastrand::Rng) -> Action {
+        let actions = self.allowed_actions();
+        actions[rng.usize(..actions.len())]
+    }
+
+    /// Bounds of the `End` reward gained from a position until the game ends,
+    /// used to normalize rewards into [0, 1]
+    fn reward_range(&self) -> (f64, f64) {
+        (0.0, 1.0)
+    }
+
+    /// Game result, declaring a draw once the ply limit is reached
+    fn result_with_limit(&self) -> Option<GameResult> {
+        self.result().or_else(|| {
+            self.ply_limit()
+                .filter(|&limit| self.ply() >= limit)
+                .map(|_| GameResult::Draw)
+        })
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Object-safe view of a `Game`, for code handling games whose type is only known at runtime.
+/// Every `Game` implements it, and `Box<dyn GameDyn>` can be cloned.
+pub trait GameDyn: Debug {
+    fn clone_box(&self) -> Box<dyn GameDyn>;
+    /// The game as `Any`, to downcast it to its concrete type for game-specific methods
+    fn as_any(&self) -> &dyn Any;
+    fn allowed_actions(&self) -> Vec<Action>;
+    fn current_player(&self) -> Player;
+    fn step_returning_reward(&mut self, action: Action) -> Result<f64, &'static str>;
+    fn result_with_limit(&self) -> Option<GameResult>;
+    fn to_tensor(&self) -> (Vec<f32>, Vec<usize>);
+    fn legal_action_mask(&self) -> Vec<bool>;
+    fn state_key(&self) -> String;
+}
+
+impl<G: Game + 'static> GameDyn for G {
+    fn clone_box(&self) -> Box<dyn GameDyn> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn allowed_actions(&self) -> Vec<Action> {
+        Game::allowed_actions(self)
+    }
+
+    fn current_player(&self) -> Player {
+        Game::current_player(self)
+    }
+
+    fn step_returning_reward(&mut self, action: Action) -> Result<f64, &'static str> {
+        Game::step_returning_reward(self, action)
+    }
+
+    fn result_with_limit(&self) -> Option<GameResult> {
+        Game::result_with_limit(self)
+    }
+
+    fn to_tensor(&self) -> (Vec<f32>, Vec<usize>) {
+        Game::to_tensor(self)
+    }
+
+    fn legal_action_mask(&self) -> Vec<bool> {
+        Game::legal_action_mask(self)
+    }
+
+    fn state_key(&self) -> String {
+        Game::state_key(self)
+    }
+}
+
+impl Clone for Box<dyn GameDyn> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Cell of a board grid, holding the piece of the player who claimed it
+pub type Cell = Option<Player>;
+
+/// Position key of a grid, its cells in order as X, O or .
+pub fn grid_key(cells: &[Cell]) -> String {
+    cells
+        .iter()
+        .map(|cell| cell.map_or('.', |player| if player == Player::X { 'X' } else { 'O' }))
+        .collect()
+}
+
+/// One-hot planes of a grid: the empty cells, then X's pieces, then O's pieces
+pub fn grid_planes(cells: &[Cell]) -> Vec<f32> {
+    let size = cells.len();
+    let mut planes = vec![0.0; 3 * size];
+    for (i, cell) in cells.iter().enumerate() {
+        let plane = match cell {
+            None => 0,
+            Some(Player::X) => 1,
+            Some(Player::O) => 2,
+        };
+        planes[plane * size + i] = 1.0;
+    }
+    planes
+}
+
+/// Number of pieces X and O have on a grid
+pub fn grid_material(cells: &[Cell]) -> Vec<usize> {
+    [Player::X, Player::O]
+        .map(|player| cells.iter().filter(|&&c| c == Some(player)).count())
+        .to_vec()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Player {
     X,
     O,
@@ -45,3 +307,123 @@ pub enum GameResult {
     Draw,
     End(f64),
 }
+
+impl GameResult {
+    /// The same result with the X and O players swapped
+    pub fn swap_players(self) -> Self {
+        match self {
+            GameResult::Win(player) => GameResult::Win(player.opponent()),
+            result => result,
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameResult::Win(player) => write!(f, "{player} wins"),
+            GameResult::Draw => write!(f, "Draw"),
+            GameResult::End(reward) => write!(f, "Game over, return {reward}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::connect4::Connect4;
+    use super::tetris::{self, Tetris};
+    use super::tictactoe::TicTacToe;
+    use super::{Game, GameDyn, GameResult, Player, grid_key, grid_material, grid_planes};
+
+    #[test]
+    fn boxed_clones_step_like_the_concrete_game() {
+        let mut concrete = TicTacToe::default();
+        let boxed: Box<dyn GameDyn> = Box::new(concrete.clone());
+        let mut clone = boxed.clone();
+        for action in [4, 0, 8] {
+            Game::step(&mut concrete, action).unwrap();
+            clone.step_returning_reward(action).unwrap();
+        }
+        assert_eq!(clone.state_key(), Game::state_key(&concrete));
+        assert_eq!(boxed.state_key(), ".........");
+    }
+
+    #[test]
+    fn boxed_games_downcast_to_their_own_type() {
+        let mut concrete = TicTacToe::default();
+        Game::step(&mut concrete, 4).unwrap();
+        let game: Box<dyn GameDyn> = Box::new(concrete.clone());
+        assert_eq!(game.as_any().downcast_ref::<TicTacToe>(), Some(&concrete));
+        assert!(game.as_any().downcast_ref::<Connect4>().is_none());
+    }
+
+    #[test]
+    fn grid_helpers_describe_the_cells() {
+        let cells = [Some(Player::X), None, Some(Player::O), Some(Player::X)];
+        assert_eq!(grid_key(&cells), "X.OX");
+        assert_eq!(grid_material(&cells), [2, 1]);
+        let planes = grid_planes(&cells);
+        assert_eq!(&planes[..4], [0.0, 1.0, 0.0, 0.0]);
+        assert_eq!(&planes[4..8], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(&planes[8..], [0.0, 0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn transpositions_are_equal() {
+        let play = |moves: &[usize]| {
+            let mut game = TicTacToe::default();
+            for &action in moves {
+                Game::step(&mut game, action).unwrap();
+            }
+            game
+        };
+        assert_eq!(play(&[0, 4, 8]), play(&[8, 4, 0]));
+        assert_ne!(play(&[0, 4, 8]), play(&[4, 0, 8]));
+    }
+
+    #[test]
+    fn results_display_for_the_cli() {
+        assert_eq!(GameResult::Win(Player::O).to_string(), "O wins");
+        assert_eq!(GameResult::Draw.to_string(), "Draw");
+        assert_eq!(GameResult::End(2.5).to_string(), "Game over, return 2.5");
+    }
+
+    #[test]
+    fn swapping_players_only_changes_wins() {
+        assert_eq!(
+            GameResult::Win(Player::X).swap_players(),
+            GameResult::Win(Player::O)
+        );
+        assert_eq!(GameResult::Draw.swap_players(), GameResult::Draw);
+        assert_eq!(GameResult::End(2.0).swap_players(), GameResult::End(2.0));
+    }
+
+    #[test]
+    fn fresh_boards_branch_within_their_action_space() {
+        let tictactoe = TicTacToe::default();
+        assert_eq!(tictactoe.branching_factor(), 9);
+        assert_eq!(tictactoe.branching_factor(), tictactoe.action_space_size());
+        let connect4 = Connect4::default();
+        assert_eq!(connect4.branching_factor(), 7);
+        assert_eq!(connect4.branching_factor(), connect4.action_space_size());
+        let tetris = Tetris::with_seed(0);
+        assert_eq!(
+            tetris.branching_factor(),
+            Game::allowed_actions(&tetris).len()
+        );
+        assert!(tetris.branching_factor() < tetris.action_space_size());
+    }
+
+    #[test]
+    fn only_tetris_accumulates_a_reward() {
+        let mut connect4 = Connect4::default();
+        for action in [3, 3, 2, 4] {
+            Game::step(&mut connect4, action).unwrap();
+            assert!(connect4.current_reward().abs() < f64::EPSILON);
+        }
+        let mut tetris = Tetris::with_seed(0);
+        assert!(tetris.current_reward().abs() < f64::EPSILON);
+        Game::step(&mut tetris, tetris::Action::HardDrop as usize).unwrap();
+        assert!(tetris.current_reward() > 0.0);
+    }
+}
@@ -1,84 +1,181 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
 use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
 
-use super::{Action, Game, GameResult, Player};
+use super::{Action, Cell, Game, GameResult, Player, grid_key, grid_material, grid_planes};
+use crate::render::Board;
 
 const ROWS: usize = 6;
 const COLS: usize = 7;
+const WIN_LEN: usize = 4;
 
-type Cell = Option<Player>;
+/// Line directions as (row, col) steps: horizontal, vertical and both diagonals
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (-1, 1)];
+
+/// Best moves on the standard board, as the moves leading to a position and the reply.
+/// The first player wins by starting in the center, and the center is the best defense.
+const OPENING_BOOK: [(&[Action], Action); 2] = [(&[], 3), (&[3], 3)];
+
+/// Opening book replies by position hash
+static BOOK: Lazy<HashMap<u64, Action>> = Lazy::new(|| {
+    OPENING_BOOK
+        .iter()
+        .map(|&(moves, reply)| {
+            let mut game = Connect4::default();
+            for &action in moves {
+                game.step(action).unwrap();
+            }
+            (game.position_hash(), reply)
+        })
+        .collect()
+});
 
 #[derive(Debug, Clone)]
 pub struct Connect4 {
-    board: [[Cell; COLS]; ROWS],
+    rows: usize,
+    cols: usize,
+    win_len: usize,
+    board: Vec<Cell>,
     current_player: Player,
     result: Option<GameResult>,
+    last_action: Option<Action>,
 }
 
 impl Connect4 {
+    /// Create an empty `rows` x `cols` board where `win_len` pieces in a line win
+    pub fn new(rows: usize, cols: usize, win_len: usize) -> Self {
+        assert!(rows > 0 && cols > 0, "Board must have at least one cell");
+        assert!(win_len > 0, "Win length must be positive");
+        Connect4 {
+            rows,
+            cols,
+            win_len,
+            board: vec![None; rows * cols],
+            current_player: Player::X,
+            result: None,
+            last_action: None,
+        }
+    }
+
     pub fn is_terminal(&self) -> bool {
         self.result.is_some()
     }
 
-    fn update_result(&mut self) {
-        // Check horizontal wins
-        for row in 0..ROWS {
-            for col in 0..COLS - 3 {
-                if let Some(player) = self.board[row][col]
-                    && (0..4).all(|i| self.board[row][col + i] == Some(player))
-                {
-                    self.result = Some(GameResult::Win(player));
-                    return;
-                }
-            }
-        }
+    /// Whether dropping a piece in `col` wins the game for the current player,
+    /// checking only the lines through the landing cell
+    pub fn is_winning_move(&self, col: usize) -> bool {
+        !self.is_terminal()
+            && col < self.cols
+            && self
+                .landing_row(col)
+                .is_some_and(|row| self.wins_through(row, col, self.current_player))
+    }
 
-        // Check vertical wins
-        for row in 0..ROWS - 3 {
-            for col in 0..COLS {
-                if let Some(player) = self.board[row][col]
-                    && (0..4).all(|i| self.board[row + i][col] == Some(player))
-                {
-                    self.result = Some(GameResult::Win(player));
-                    return;
-                }
-            }
+    /// The same position with the X and O pieces swapped, so the player to move changes
+    /// sides. Flipping twice gives back the original position. With X a piece ahead, the
+    /// flipped position has O ahead and fails `validate`.
+    #[allow(dead_code)]
+    pub fn flip_perspective(&self) -> Self {
+        Connect4 {
+            board: self
+                .board
+                .iter()
+                .map(|cell| cell.map(Player::opponent))
+                .collect(),
+            current_player: self.current_player.opponent(),
+            result: self.result.map(GameResult::swap_players),
+            ..self.clone()
         }
+    }
 
-        // Check diagonal wins (bottom-left to top-right)
-        for row in 3..ROWS {
-            for col in 0..COLS - 3 {
-                if let Some(player) = self.board[row][col]
-                    && (0..4).all(|i| self.board[row - i][col + i] == Some(player))
-                {
-                    self.result = Some(GameResult::Win(player));
-                    return;
-                }
-            }
+    /// Columns where a piece of `player` would complete a line, checking only the lines
+    /// through each landing cell. Two of them at once are a forced win.
+    pub fn threats(&self, player: Player) -> Vec<usize> {
+        if self.is_terminal() {
+            return vec![];
         }
+        (0..self.cols)
+            .filter(|&col| {
+                self.landing_row(col)
+                    .is_some_and(|row| self.wins_through(row, col, player))
+            })
+            .collect()
+    }
 
-        // Check diagonal wins (top-left to bottom-right)
-        for row in 0..ROWS - 3 {
-            for col in 0..COLS - 3 {
-                if let Some(player) = self.board[row][col]
-                    && (0..4).all(|i| self.board[row + i][col + i] == Some(player))
-                {
-                    self.result = Some(GameResult::Win(player));
-                    return;
-                }
+    fn position_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn cell(&self, row: usize, col: usize) -> Cell {
+        self.board[row * self.cols + col]
+    }
+
+    /// Cell one step away from (row, col) in the direction (dr, dc), if on the board
+    fn neighbor(&self, row: usize, col: usize, (dr, dc): (isize, isize)) -> Option<(usize, usize)> {
+        let row = row.checked_add_signed(dr)?;
+        let col = col.checked_add_signed(dc)?;
+        (row < self.rows && col < self.cols).then_some((row, col))
+    }
+
+    /// Number of pieces of `player` in the line through (row, col) along (dr, dc),
+    /// counting (row, col) itself
+    fn line_length(
+        &self,
+        row: usize,
+        col: usize,
+        player: Player,
+        (dr, dc): (isize, isize),
+    ) -> usize {
+        let count = |dir| {
+            std::iter::successors(self.neighbor(row, col, dir), |&(r, c)| {
+                self.neighbor(r, c, dir)
+            })
+            .take_while(|&(r, c)| self.cell(r, c) == Some(player))
+            .count()
+        };
+        1 + count((dr, dc)) + count((-dr, -dc))
+    }
+
+    /// Whether a piece of `player` at (row, col) is part of a winning line
+    fn wins_through(&self, row: usize, col: usize, player: Player) -> bool {
+        DIRECTIONS
+            .iter()
+            .any(|&dir| self.line_length(row, col, player, dir) >= self.win_len)
+    }
+
+    /// Row a piece dropped in `col` lands on, if the column isn't full
+    fn landing_row(&self, col: usize) -> Option<usize> {
+        (0..self.rows)
+            .rev()
+            .find(|&row| self.cell(row, col).is_none())
+    }
+
+    /// Check for wins of the player who just moved on the lines through the given board
+    /// cells, and for a full board. Called before the player switch.
+    fn update_result(&mut self, cells: &[usize]) {
+        let mover = self.current_player;
+        for &i in cells {
+            let (row, col) = (i / self.cols, i % self.cols);
+            if self.cell(row, col) == Some(mover) && self.wins_through(row, col, mover) {
+                self.result = Some(GameResult::Win(mover));
+                return;
             }
         }
 
         // Check for draw (board full)
-        if self.board[0].iter().all(Option::is_some) {
+        if (0..self.cols).all(|col| self.cell(0, col).is_some()) {
             self.result = Some(GameResult::Draw);
         }
     }
 
     fn drop_piece(&mut self, col: usize) -> Result<(), &'static str> {
         // Find the lowest empty row in this column
-        for row in (0..ROWS).rev() {
-            if self.board[row][col].is_none() {
-                self.board[row][col] = Some(self.current_player);
+        for row in (0..self.rows).rev() {
+            if self.cell(row, col).is_none() {
+                self.board[row * self.cols + col] = Some(self.current_player);
                 return Ok(());
             }
         }
@@ -88,33 +185,53 @@ impl Connect4 {
 
 impl Default for Connect4 {
     fn default() -> Self {
-        Connect4 {
-            board: [[None; COLS]; ROWS],
-            current_player: Player::X,
-            result: None,
-        }
+        Connect4::new(ROWS, COLS, WIN_LEN)
+    }
+}
+
+// Games compare by their rules, pieces and player to move. Move orders reaching the same
+// pieces are transpositions, so the result and last action cached from them are left out.
+impl PartialEq for Connect4 {
+    fn eq(&self, other: &Self) -> bool {
+        self.rows == other.rows
+            && self.cols == other.cols
+            && self.win_len == other.win_len
+            && self.board == other.board
+            && self.current_player == other.current_player
+    }
+}
+
+impl Eq for Connect4 {}
+
+impl Hash for Connect4 {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rows.hash(state);
+        self.cols.hash(state);
+        self.win_len.hash(state);
+        self.board.hash(state);
+        self.current_player.hash(state);
     }
 }
 
 impl fmt::Display for Connect4 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Print column numbers
-        for col in 0..COLS {
+        for col in 0..self.cols {
             write!(f, "{col} ")?;
         }
         writeln!(f)?;
 
         // Print board
-        for row in 0..ROWS {
-            for col in 0..COLS {
-                if let Some(player) = self.board[row][col] {
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                if let Some(player) = self.cell(row, col) {
                     write!(f, "{player}")?;
                 } else {
                     write!(f, ".")?;
                 }
                 write!(f, " ")?;
             }
-            if row < ROWS - 1 {
+            if row < self.rows - 1 {
                 writeln!(f)?;
             }
         }
@@ -122,13 +239,29 @@ impl fmt::Display for Connect4 {
     }
 }
 
+impl Board for Connect4 {
+    fn dimensions(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    fn owner(&self, row: usize, col: usize) -> Option<Player> {
+        self.cell(row, col)
+    }
+}
+
 impl Game for Connect4 {
     fn print_instructions(&self) {
         println!("Connect 4 with MCTS Agent");
         println!("=========================");
         println!("You are X, MCTS agent is O");
-        println!("Enter column number (0-6) to drop your piece.");
-        println!("Connect 4 pieces horizontally, vertically, or diagonally to win!");
+        println!(
+            "Enter column number (0-{}) to drop your piece.",
+            self.cols - 1
+        );
+        println!(
+            "Connect {} pieces horizontally, vertically, or diagonally to win!",
+            self.win_len
+        );
         println!();
     }
 
@@ -141,20 +274,31 @@ impl Game for Connect4 {
             return Vec::new();
         }
         // A column is playable if the top cell is empty
-        (0..COLS)
-            .filter(|&col| self.board[0][col].is_none())
+        (0..self.cols)
+            .filter(|&col| self.cell(0, col).is_none())
             .collect()
     }
 
+    fn action_name(&self, action: Action) -> String {
+        format!("column {action}")
+    }
+
+    /// Accepts `column N` or just `N`
+    fn action_from_name(&self, name: &str) -> Result<Action, &'static str> {
+        let name = name.trim();
+        let col = name.strip_prefix("column").unwrap_or(name);
+        col.trim().parse().map_err(|_| "Unknown action")
+    }
+
     fn current_player(&self) -> Player {
         self.current_player
     }
 
     fn step(&mut self, action: Action) -> Result<(), &'static str> {
-        if action >= COLS {
+        if action >= self.cols {
             return Err("Column out of bounds");
         }
-        if self.board[0][action].is_some() {
+        if self.cell(0, action).is_some() {
             return Err("Column is full");
         }
         if self.is_terminal() {
@@ -162,12 +306,334 @@ impl Game for Connect4 {
         }
 
         self.drop_piece(action)?;
-        self.update_result();
-        self.current_player = self.current_player.opponent();
+        self.last_action = Some(action);
+        let region = self
+            .affected_region(action)
+            .unwrap_or_else(|| (0..self.board.len()).collect());
+        self.update_result(&region);
+        self.current_player = self.next_player();
         Ok(())
     }
 
-    fn current_reward(&self) -> f64 {
-        0.0
+    /// Shape `[3, rows, cols]`: planes for empty, X and O cells
+    fn to_tensor(&self) -> (Vec<f32>, Vec<usize>) {
+        (grid_planes(&self.board), vec![3, self.rows, self.cols])
+    }
+
+    fn action_space_size(&self) -> usize {
+        self.cols
+    }
+
+    fn legal_action_mask(&self) -> Vec<bool> {
+        let playing = !self.is_terminal();
+        (0..self.cols)
+            .map(|col| playing && self.cell(0, col).is_none())
+            .collect()
+    }
+
+    /// A drop can only complete lines through its own column
+    fn affected_region(&self, action: Action) -> Option<Vec<usize>> {
+        Some((0..self.rows).map(|row| row * self.cols + action).collect())
+    }
+
+    fn symmetries(&self) -> Vec<Vec<Action>> {
+        let mirror: Vec<Action> = (0..self.cols).rev().collect();
+        let symmetric = (0..self.rows).all(|row| {
+            (0..self.cols).all(|col| self.cell(row, col) == self.cell(row, mirror[col]))
+        });
+        if symmetric { vec![mirror] } else { vec![] }
+    }
+
+    fn ply(&self) -> usize {
+        self.board.iter().filter(|cell| cell.is_some()).count()
+    }
+
+    fn last_action(&self) -> Option<Action> {
+        self.last_action
+    }
+
+    fn material(&self) -> Vec<usize> {
+        grid_material(&self.board)
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        for row in 1..self.rows {
+            for col in 0..self.cols {
+                if self.cell(row - 1, col).is_some() && self.cell(row, col).is_none() {
+                    return Err("Piece floating above an empty cell");
+                }
+            }
+        }
+        let material = self.material();
+        // X moves first, so X has as many pieces as O or one more
+        let (x, o) = (material[0], material[1]);
+        if x != o && x != o + 1 {
+            return Err("Players have unbalanced piece counts");
+        }
+        Ok(())
+    }
+
+    /// Cells row by row from the top, as X, O or .
+    fn state_key(&self) -> String {
+        grid_key(&self.board)
+    }
+
+    /// Only the dropped piece can complete a line
+    fn result_after(&self, action: Action) -> Option<GameResult> {
+        if self.is_terminal() || action >= self.cols {
+            return None;
+        }
+        let row = self.landing_row(action)?;
+        if self.is_winning_move(action) {
+            Some(GameResult::Win(self.current_player))
+        } else if row == 0 && (0..self.cols).all(|col| col == action || self.cell(0, col).is_some())
+        {
+            Some(GameResult::Draw)
+        } else {
+            None
+        }
+    }
+
+    fn is_winning_action(&self, action: Action) -> bool {
+        self.is_winning_move(action)
+    }
+
+    fn book_move(&self) -> Option<Action> {
+        BOOK.get(&self.position_hash()).copied()
+    }
+
+    /// Win if possible, otherwise block the opponent's win
+    fn heuristic_move(&self) -> Option<Action> {
+        if self.is_terminal() {
+            return None;
+        }
+        (0..self.cols)
+            .find(|&col| self.is_winning_action(col))
+            .or_else(|| {
+                self.threats(self.current_player.opponent())
+                    .first()
+                    .copied()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(moves: &[Action]) -> Connect4 {
+        let mut game = Connect4::default();
+        for &action in moves {
+            game.step(action).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn ply_counts_each_step() {
+        let mut game = Connect4::default();
+        for (ply, action) in [3, 3, 2, 4, 0].into_iter().enumerate() {
+            assert_eq!(game.ply(), ply);
+            game.step(action).unwrap();
+        }
+        assert_eq!(game.ply(), 5);
+        assert!(game.step(9).is_err());
+        assert_eq!(game.ply(), 5);
+    }
+
+    #[test]
+    fn small_boards_win_with_shorter_lines() {
+        let mut game = Connect4::new(4, 5, 3);
+        for action in [0, 0, 1, 1] {
+            game.step(action).unwrap();
+        }
+        assert_eq!(game.allowed_actions().len(), 5);
+        assert!(game.is_winning_move(2));
+        game.step(2).unwrap();
+        assert_eq!(game.result(), Some(GameResult::Win(Player::X)));
+    }
+
+    #[test]
+    fn diagonal_wins_on_a_square_board() {
+        let mut game = Connect4::new(5, 5, 4);
+        for action in [0, 1, 1, 2, 3, 2, 2, 3, 4, 3] {
+            game.step(action).unwrap();
+        }
+        assert_eq!(game.result(), None);
+        game.step(3).unwrap();
+        assert_eq!(game.result(), Some(GameResult::Win(Player::X)));
+        assert_eq!(game.to_string().lines().count(), 6);
+    }
+
+    #[test]
+    fn only_mirror_images_are_symmetric() {
+        assert_eq!(Connect4::default().symmetries().len(), 1);
+        assert_eq!(play(&[3, 3]).symmetries().len(), 1);
+        assert!(play(&[0]).symmetries().is_empty());
+    }
+
+    #[test]
+    fn local_result_checks_match_a_full_scan() {
+        let game = Connect4::default();
+        assert_eq!(game.affected_region(3), Some(vec![3, 10, 17, 24, 31, 38]));
+
+        let every_cell: Vec<usize> = (0..game.rows * game.cols).collect();
+        let mut rng = fastrand::Rng::with_seed(9);
+        for _ in 0..200 {
+            let mut game = Connect4::default();
+            while game.result().is_none() {
+                let mover = game.current_player();
+                game.step(game.rollout_action(&mut rng)).unwrap();
+                let mut full = game.clone();
+                full.result = None;
+                full.current_player = mover;
+                full.update_result(&every_cell);
+                assert_eq!(full.result, game.result(), "{game}");
+            }
+        }
+    }
+
+    #[test]
+    fn heuristic_wins_first_then_blocks() {
+        let threat = play(&[0, 6, 1, 6, 2]);
+        assert_eq!(threat.heuristic_move(), Some(3));
+        let both = play(&[0, 6, 1, 6, 2, 6]);
+        assert_eq!(both.heuristic_move(), Some(3));
+        assert_eq!(play(&[3, 3]).heuristic_move(), None);
+    }
+
+    #[test]
+    fn result_after_matches_stepping() {
+        for moves in [&[][..], &[0, 6, 1, 6, 2, 5], &[3, 3, 3, 3, 3, 3]] {
+            let game = play(moves);
+            for action in 0..game.cols {
+                let mut next = game.clone();
+                let stepped = next.step(action).ok().and_then(|()| next.result());
+                assert_eq!(
+                    game.result_after(action),
+                    stepped,
+                    "{moves:?} then {action}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn winning_moves_complete_a_line_of_the_player_to_move() {
+        let mut game = play(&[0, 1, 0, 1, 0, 1]);
+        assert!(game.is_winning_move(0));
+        assert!(!game.is_winning_move(1));
+        assert!(!game.is_winning_move(7));
+        game.step(0).unwrap();
+        assert_eq!(game.result(), Some(GameResult::Win(Player::X)));
+        assert!(!game.is_winning_move(1));
+    }
+
+    #[test]
+    fn winning_actions_agree_with_the_result_after() {
+        for moves in [&[0, 6, 1, 6, 2, 5][..], &[0, 1, 0, 1, 0, 1], &[3, 3]] {
+            let game = play(moves);
+            for action in 0..game.cols {
+                let wins = game.result_after(action) == Some(GameResult::Win(Player::X));
+                assert_eq!(
+                    game.is_winning_action(action),
+                    wins,
+                    "{moves:?} then {action}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn full_columns_are_masked_out() {
+        let game = play(&[3, 3, 3, 3, 3, 3]);
+        assert_eq!(game.action_space_size(), 7);
+        let mask = game.legal_action_mask();
+        assert_eq!(mask, [true, true, true, false, true, true, true]);
+        let allowed: Vec<Action> = (0..7).filter(|&col| mask[col]).collect();
+        assert_eq!(allowed, game.allowed_actions());
+
+        let won = play(&[0, 1, 0, 1, 0, 1, 0]);
+        assert!(won.legal_action_mask().iter().all(|&legal| !legal));
+    }
+
+    #[test]
+    fn only_the_mover_wins_on_a_board_with_both_lines() {
+        let mut game = Connect4::default();
+        // O already has the bottom row 0-3, and X three pieces on top of column 0
+        for col in 0..4 {
+            game.board[5 * game.cols + col] = Some(Player::O);
+        }
+        for row in 2..5 {
+            game.board[row * game.cols] = Some(Player::X);
+        }
+        let mut quiet = game.clone();
+        quiet.step(1).unwrap();
+        assert_eq!(quiet.result(), None);
+        game.step(0).unwrap();
+        assert_eq!(game.result(), Some(GameResult::Win(Player::X)));
+    }
+
+    #[test]
+    fn action_names_parse_back() {
+        let game = Connect4::default();
+        for action in game.allowed_actions() {
+            assert_eq!(game.action_from_name(&game.action_name(action)), Ok(action));
+        }
+        assert_eq!(game.action_from_name(" 4 "), Ok(4));
+        assert!(game.action_from_name("left").is_err());
+    }
+
+    #[test]
+    fn flipping_swaps_the_sides() {
+        let game = play(&[3, 3, 2]);
+        let flipped = game.flip_perspective();
+        assert_eq!(flipped.current_player(), Player::X);
+        assert_eq!(flipped.material(), [1, 2]);
+        let back = flipped.flip_perspective();
+        assert_eq!(back, game);
+        assert_eq!(back.result(), game.result());
+
+        let won = play(&[0, 1, 0, 1, 0, 1, 0]);
+        assert_eq!(
+            won.flip_perspective().result(),
+            Some(GameResult::Win(Player::O))
+        );
+    }
+
+    #[test]
+    fn validation_rejects_impossible_positions() {
+        assert_eq!(play(&[3, 3, 2]).validate(), Ok(()));
+        let mut o_first = Connect4::default();
+        o_first.board[5 * o_first.cols] = Some(Player::O);
+        assert_eq!(
+            o_first.validate(),
+            Err("Players have unbalanced piece counts")
+        );
+        let mut floating = play(&[3, 3]);
+        floating.board[3 * floating.cols] = Some(Player::X);
+        floating.board[3 * floating.cols + 1] = Some(Player::O);
+        assert_eq!(
+            floating.validate(),
+            Err("Piece floating above an empty cell")
+        );
+    }
+
+    #[test]
+    fn threats_are_the_winning_columns() {
+        let game = play(&[0, 6, 1, 6, 2, 5]);
+        assert_eq!(game.threats(Player::X), [3]);
+        assert!(game.is_winning_move(3));
+        assert!(!game.is_winning_move(4));
+        assert!(game.threats(Player::O).is_empty());
+    }
+
+    #[test]
+    fn state_keys_identify_positions() {
+        let key = play(&[0, 1, 2]).state_key();
+        assert_eq!(key.len(), 42);
+        assert_eq!(key, play(&[2, 1, 0]).state_key());
+        assert_ne!(key, play(&[0, 2, 1]).state_key());
+        assert_eq!(Connect4::default().state_key(), ".".repeat(42));
     }
 }
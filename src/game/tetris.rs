@@ -2,17 +2,21 @@ use once_cell::sync::OnceCell;
 use rand::{Rng, SeedableRng};
 use raylib::color::Color;
 use raylib::prelude::*;
+use std::fmt::{self, Write};
+use std::hash::{Hash, Hasher};
 use std::thread;
 
 use crate::game::{Game, GameResult, Player};
-
-const HALF_LINEWIDTH: i32 = 1;
-const SQUARE_SIZE: i32 = 32;
+use crate::render::{SQUARE_SIZE, cell_position, draw_cell};
 
 // Store the main thread ID to ensure rendering only happens on main thread
 static MAIN_THREAD_ID: OnceCell<thread::ThreadId> = OnceCell::new();
-const DECK_SIZE: usize = 2 * NUM_TETROMINOES; // To implement the 7-bag system
+const DECK_SIZE: usize = 2 * NUM_TETROMINOES; // Two batches, so the next one is drawn ahead
 const NUM_PREVIEW: usize = 2;
+/// Frames during which cleared lines flash before the board collapses
+const LINE_CLEAR_FRAMES: usize = 20;
+/// Frames between gravity ticks when a human plays
+const GRAVITY_FRAMES: u32 = 10;
 
 #[repr(u8)]
 #[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
@@ -25,19 +29,23 @@ pub enum Action {
     SoftDrop = 4,
     HardDrop = 5,
     Hold = 6,
+    /// Move down to the resting position without locking, unlike `HardDrop`
+    SonicDrop = 7,
 }
 
+const NUM_ACTIONS: usize = 8;
+
 impl From<u8> for Action {
     fn from(value: u8) -> Self {
         match value {
-            0 => Action::NoOp,
             1 => Action::Left,
             2 => Action::Right,
             3 => Action::Rotate,
             4 => Action::SoftDrop,
             5 => Action::HardDrop,
             6 => Action::Hold,
-            _ => Action::NoOp, // Default to NoOp for invalid values
+            7 => Action::SonicDrop,
+            _ => Action::NoOp, // 0, and the default for invalid values
         }
     }
 }
@@ -48,7 +56,7 @@ const GRID_SIZE: usize = NUM_ROWS * NUM_COLS;
 
 const INITIAL_TICKS_PER_FALL: usize = 3; // how many ticks before the tetromino naturally falls down of one square
 
-const LINES_PER_LEVEL: usize = 10;
+const LINES_PER_LEVEL: u32 = 10;
 // Revisit scoring with level. See https://tetris.wiki/Scoring
 const SCORE_SOFT_DROP: usize = 1;
 #[allow(dead_code)]
@@ -58,20 +66,166 @@ const REWARD_HARD_DROP: f32 = 0.02;
 const REWARD_ROTATE: f32 = 0.01;
 const REWARD_INVALID_ACTION: f32 = 0.0;
 
-const SCORE_COMBO: [i32; 5] = [0, 100, 300, 500, 1000];
+// Score to beat unless another personal best is configured
+const PERSONAL_BEST: usize = 67890;
+
+// Default score and reward by number of lines cleared at once
+const SCORE_COMBO: [usize; 5] = [0, 100, 300, 500, 1000];
 const REWARD_COMBO: [f32; 5] = [0.0, 0.1, 0.3, 0.5, 1.0];
+// Upper end of the return of a random rollout, earned by drops and rotations
+const ROLLOUT_RETURN: f64 = 3.0;
+
+/// Weights of the reward for changes to the stack when a tetromino locks.
+/// Each new hole and each row of added height costs its weight, and removing them earns it.
+#[derive(Clone, Copy, Debug)]
+pub struct StackShaping {
+    pub hole_weight: f32,
+    pub height_weight: f32,
+}
+
+impl StackShaping {
+    fn reward(self, hole_change: f32, height_change: f32) -> f32 {
+        -self.hole_weight * hole_change - self.height_weight * height_change
+    }
+}
+
+/// Randomizer choosing the sequence of tetrominoes
+pub trait PieceGenerator: fmt::Debug + Send + Sync {
+    /// Draw the next tetromino id
+    fn next(&mut self, rng: &mut rand::rngs::SmallRng) -> usize;
+
+    /// Replace `hidden`, a run of drawn pieces the player can't see yet, with an equally
+    /// likely run. Shuffling suits generators that draw batches of fixed pieces, like bags.
+    fn conceal(&self, hidden: &mut [usize], rng: &mut rand::rngs::SmallRng) {
+        shuffle(hidden, rng);
+    }
+
+    /// Whether every `NUM_TETROMINOES` draws hold each tetromino exactly once
+    fn draws_bags(&self) -> bool {
+        false
+    }
+
+    fn clone_box(&self) -> Box<dyn PieceGenerator>;
+}
+
+impl Clone for Box<dyn PieceGenerator> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// The 7-bag system: each tetromino once per bag, in random order
+#[derive(Debug, Clone, Default)]
+pub struct SevenBag {
+    /// Rest of the current bag, the next piece last
+    bag: Vec<usize>,
+}
+
+impl PieceGenerator for SevenBag {
+    fn next(&mut self, rng: &mut rand::rngs::SmallRng) -> usize {
+        if self.bag.is_empty() {
+            self.bag.extend(0..NUM_TETROMINOES);
+            shuffle(&mut self.bag, rng);
+            self.bag.reverse();
+        }
+        self.bag.pop().unwrap()
+    }
+
+    fn draws_bags(&self) -> bool {
+        true
+    }
+
+    fn clone_box(&self) -> Box<dyn PieceGenerator> {
+        Box::new(self.clone())
+    }
+}
+
+/// Every tetromino equally likely at each draw, allowing long droughts and repeats
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UniformRandom;
+
+impl PieceGenerator for UniformRandom {
+    fn next(&mut self, rng: &mut rand::rngs::SmallRng) -> usize {
+        rng.random_range(0..NUM_TETROMINOES)
+    }
+
+    fn conceal(&self, hidden: &mut [usize], rng: &mut rand::rngs::SmallRng) {
+        for piece in hidden {
+            *piece = rng.random_range(0..NUM_TETROMINOES);
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn PieceGenerator> {
+        Box::new(*self)
+    }
+}
+
+/// Fisher-Yates shuffle
+fn shuffle(items: &mut [usize], rng: &mut rand::rngs::SmallRng) {
+    for i in (1..items.len()).rev() {
+        let j = rng.random_range(0..=i);
+        items.swap(i, j);
+    }
+}
+
+/// Delayed Auto Shift for human input: a held direction moves once, then again after
+/// `das` frames and every `arr` frames from there
+#[derive(Debug, Clone)]
+pub struct AutoShift {
+    das: u32,
+    arr: u32,
+    held: Option<Action>,
+    frames_held: u32,
+}
+
+impl AutoShift {
+    pub fn new(das: u32, arr: u32) -> Self {
+        AutoShift {
+            das,
+            arr: arr.max(1),
+            held: None,
+            frames_held: 0,
+        }
+    }
+
+    /// Move to make this frame, given the direction held, if any
+    pub fn update(&mut self, held: Option<Action>) -> Option<Action> {
+        if held != self.held {
+            self.held = held;
+            self.frames_held = 0;
+            return held;
+        }
+        self.frames_held += 1;
+        let repeat =
+            self.frames_held >= self.das && (self.frames_held - self.das).is_multiple_of(self.arr);
+        held.filter(|_| repeat)
+    }
+}
 
 #[derive(Debug)]
 pub struct Client {
-    total_cols: i32,
-    total_rows: i32,
-    ui_rows: i32,
-    deck_rows: i32,
+    total_cols: usize,
+    total_rows: usize,
+    ui_rows: usize,
+    deck_rows: usize,
     rl: RaylibHandle,
     thread: RaylibThread,
 }
 
+impl Client {
+    /// Window row of the top of the next pieces and the held tetromino
+    fn deck_top(&self) -> usize {
+        1 + self.ui_rows + 1
+    }
+
+    /// Window row of the top of the main grid
+    fn grid_top(&self) -> usize {
+        self.deck_top() + self.deck_rows + 1
+    }
+}
+
 #[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Tetris {
     rewards: f32,
     is_terminal: bool,
@@ -84,7 +238,9 @@ pub struct Tetris {
     ticks_per_fall: usize,
     score: usize,
     can_swap: bool,
+    /// Drawn tetrominoes, as two batches refilled in turn from the generator
     tetromino_deck: [usize; DECK_SIZE],
+    generator: Box<dyn PieceGenerator>,
     hold_tetromino: Option<usize>,
     cur_position_in_deck: usize,
     cur_tetromino: usize,
@@ -100,10 +256,37 @@ pub struct Tetris {
     atn_count_rotate: u32,
     atn_count_hold: u32,
     tetromino_counts: [u32; NUM_TETROMINOES],
+    /// Bit set of the tetrominoes spawned from the current bag
+    bag_spawned: u8,
+    /// Whether the tetromino falls on its own
+    gravity: bool,
+    noop_allowed: bool,
+    sonic_drop_allowed: bool,
+    personal_best: usize,
+    /// Rows cleared by the last placed tetromino, as indices before the board collapsed
+    cleared_rows: Vec<usize>,
+    /// Tick at which the episode ends, keeping its score
+    tick_limit: Option<usize>,
+    last_action: Option<Action>,
+    /// Last sideways move or rotation of the falling tetromino, `None` once it falls
+    last_shift: Option<Action>,
+    /// `last_shift` when the last tetromino locked
+    last_lock_shift: Option<Action>,
+    /// Reward for how each lock changes the stack, if shaping is on
+    stack_shaping: Option<StackShaping>,
+    /// Score by number of lines cleared at once, the last entry applying to larger clears
+    score_combo: Vec<usize>,
+    /// Reward by number of lines cleared at once, the last entry applying to larger clears
+    reward_combo: Vec<f32>,
 }
 
 impl Tetris {
     pub fn new() -> Self {
+        Self::with_seed(rand::rng().random())
+    }
+
+    /// Create a game whose tetrominoes are drawn from an RNG seeded with `seed`
+    pub fn with_seed(seed: u64) -> Self {
         let n_rows = NUM_ROWS;
         let n_cols = NUM_COLS;
 
@@ -113,13 +296,14 @@ impl Tetris {
             n_rows,
             n_cols,
             grid: [0; GRID_SIZE],
-            rng: rand::rngs::SmallRng::seed_from_u64(rand::rng().random()),
+            rng: rand::rngs::SmallRng::seed_from_u64(seed),
             tick: 0,
             tick_fall: 0,
             ticks_per_fall: INITIAL_TICKS_PER_FALL,
             score: 0,
             can_swap: true,
             tetromino_deck: [0; DECK_SIZE],
+            generator: Box::new(SevenBag::default()),
             hold_tetromino: None,
             cur_position_in_deck: 0,
             cur_tetromino: 0,
@@ -135,59 +319,273 @@ impl Tetris {
             atn_count_rotate: 0,
             atn_count_hold: 0,
             tetromino_counts: [0; NUM_TETROMINOES],
+            bag_spawned: 0,
+            gravity: true,
+            noop_allowed: true,
+            sonic_drop_allowed: false,
+            personal_best: PERSONAL_BEST,
+            cleared_rows: Vec::new(),
+            tick_limit: None,
+            last_action: None,
+            last_shift: None,
+            last_lock_shift: None,
+            stack_shaping: None,
+            score_combo: SCORE_COMBO.to_vec(),
+            reward_combo: REWARD_COMBO.to_vec(),
         };
         tetris.reset();
         tetris
     }
 
-    fn restore_grid(&mut self) {
-        self.grid.fill(0);
+    /// Create a game drawing its tetrominoes from `generator` instead of 7-piece bags
+    pub fn with_generator(generator: impl PieceGenerator + 'static) -> Self {
+        let mut tetris = Self::new();
+        tetris.generator = Box::new(generator);
+        tetris.reset();
+        tetris
     }
 
-    fn refill_and_shuffle(array: &mut [usize], rng: &mut rand::rngs::SmallRng) {
-        // Hold can change the deck distribution, so need to refill
-        for (i, item) in array.iter_mut().enumerate() {
-            *item = i;
+    /// Create a game from a grid of `.` (empty) and `#` (filled) rows, with `current` as the
+    /// falling tetromino at its spawn position and `hold` as the held tetromino.
+    /// The game is already over if the grid blocks the spawn position.
+    pub fn from_grid_str(
+        s: &str,
+        current: usize,
+        hold: Option<usize>,
+    ) -> Result<Self, &'static str> {
+        if current >= NUM_TETROMINOES || hold.is_some_and(|h| h >= NUM_TETROMINOES) {
+            return Err("Unknown tetromino");
         }
 
-        // Fisher-Yates shuffle
-        for i in (1..NUM_TETROMINOES).rev() {
-            let j = rng.random_range(0..=i);
-            array.swap(i, j);
+        let mut tetris = Self::new();
+        let rows: Vec<&str> = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if rows.len() != tetris.n_rows {
+            return Err("Wrong number of rows");
+        }
+        for (r, row) in rows.iter().enumerate() {
+            if row.chars().count() != tetris.n_cols {
+                return Err("Wrong number of columns");
+            }
+            for (c, ch) in row.chars().enumerate() {
+                tetris.grid[r * tetris.n_cols + c] = match ch {
+                    '.' => 0,
+                    '#' => 1,
+                    _ => return Err("Unexpected grid character"),
+                };
+            }
+        }
+
+        tetris.tetromino_counts[tetris.cur_tetromino] -= 1;
+        tetris.tetromino_counts[current] += 1;
+        tetris.cur_tetromino = current;
+        tetris.tetromino_deck[tetris.cur_position_in_deck] = current;
+        tetris.hold_tetromino = hold;
+        tetris.move_to_spawn();
+        if !tetris.fits(
+            current,
+            0,
+            tetris.cur_tetromino_row,
+            tetris.cur_tetromino_col,
+        ) {
+            tetris.is_terminal = true; // Game over, as when a spawn is blocked in play
+        }
+        tetris.validate()?;
+        Ok(tetris)
+    }
+
+    /// Whether a tetromino with the given rotation fits in the grid with its top-left at (row, col)
+    fn fits(&self, tetromino: usize, rot: usize, row: usize, col: usize) -> bool {
+        let rows = TETROMINO_FILL_ROWS[tetromino][rot] as usize;
+        let cols = TETROMINO_FILL_COLS[tetromino][rot] as usize;
+        if row + rows > self.n_rows || col + cols > self.n_cols {
+            return false;
+        }
+        (0..rows).all(|r| {
+            (0..cols).all(|c| {
+                TETROMINOES[tetromino][rot][r][c] == 0
+                    || self.grid[(r + row) * self.n_cols + c + col] == 0
+            })
+        })
+    }
+
+    /// Top-left (row, col) where the current tetromino would lock on a hard drop
+    pub fn drop_position(&self) -> (usize, usize) {
+        let (tetromino, rot) = (self.cur_tetromino, self.cur_tetromino_rot);
+        let col = self.cur_tetromino_col;
+        let mut row = self.cur_tetromino_row;
+        while self.fits(tetromino, rot, row + 1, col) {
+            row += 1;
+        }
+        (row, col)
+    }
+
+    fn restore_grid(&mut self) {
+        self.grid.fill(0);
+    }
+
+    /// Overwrite the deck slots in `range` with pieces from the generator.
+    /// Hold can change the pieces of the deck, so batches are always redrawn in full.
+    fn draw_batch(&mut self, range: std::ops::Range<usize>) {
+        for slot in &mut self.tetromino_deck[range] {
+            *slot = self.generator.next(&mut self.rng);
         }
     }
 
     fn initialize_deck(&mut self) {
-        // Implements a 7-bag system. The deck is composed of two bags.
-        Self::refill_and_shuffle(&mut self.tetromino_deck[0..NUM_TETROMINOES], &mut self.rng); // First bag
-        Self::refill_and_shuffle(
-            &mut self.tetromino_deck[NUM_TETROMINOES..DECK_SIZE],
-            &mut self.rng,
-        ); // Second bag
-        self.cur_position_in_deck = 0;
-        self.cur_tetromino = self.tetromino_deck[self.cur_position_in_deck];
+        // The deck is composed of two batches, bags for the 7-bag system
+        self.draw_batch(0..NUM_TETROMINOES);
+        // The first spawn wraps around to the first piece of the first batch and draws the
+        // second batch. Starting at position 0 would skip that piece.
+        self.cur_position_in_deck = DECK_SIZE - 1;
     }
 
     fn spawn_new_tetromino(&mut self) {
         self.cur_position_in_deck = (self.cur_position_in_deck + 1) % DECK_SIZE;
         self.cur_tetromino = self.tetromino_deck[self.cur_position_in_deck];
-        self.cur_tetromino_rot = 0;
+
+        if self.cur_position_in_deck.is_multiple_of(NUM_TETROMINOES) {
+            self.bag_spawned = 0;
+        }
+        debug_assert!(
+            !self.generator.draws_bags() || self.bag_spawned & (1 << self.cur_tetromino) == 0,
+            "Tetromino {} spawned twice in a bag",
+            self.cur_tetromino
+        );
+        self.bag_spawned |= 1 << self.cur_tetromino;
 
         if self.cur_position_in_deck == 0 {
-            // Now using the first bag, so shuffle the second bag
-            Self::refill_and_shuffle(
-                &mut self.tetromino_deck[NUM_TETROMINOES..DECK_SIZE],
-                &mut self.rng,
-            );
+            // Now using the first batch, so draw the second batch
+            self.draw_batch(NUM_TETROMINOES..DECK_SIZE);
         } else if self.cur_position_in_deck == NUM_TETROMINOES {
-            // Now using the second bag, so shuffle the first bag
-            Self::refill_and_shuffle(&mut self.tetromino_deck[0..NUM_TETROMINOES], &mut self.rng);
+            // Now using the second batch, so draw the first batch
+            self.draw_batch(0..NUM_TETROMINOES);
         }
 
-        self.cur_tetromino_col = self.n_cols / 2;
+        self.move_to_spawn();
+        self.tetromino_counts[self.cur_tetromino] += 1;
+    }
+
+    /// Put the current tetromino at the spawn position and rotation
+    fn move_to_spawn(&mut self) {
+        self.last_shift = None;
+        self.cur_tetromino_rot = 0;
+        self.cur_tetromino_col = self.spawn_col(self.cur_tetromino);
         self.cur_tetromino_row = 0;
         self.tick_fall = 0;
-        self.tetromino_counts[self.cur_tetromino] += 1;
+    }
+
+    /// Leftmost column of `tetromino` at spawn, centering it horizontally
+    fn spawn_col(&self, tetromino: usize) -> usize {
+        self.n_cols
+            .saturating_sub(TETROMINO_FILL_COLS[tetromino][0] as usize)
+            / 2
+    }
+
+    /// Whether `NoOp` is offered by `allowed_actions`.
+    /// Gravity keeps advancing on every action, so searches can skip waiting moves.
+    /// `NoOp` is still offered when no other action is possible.
+    pub fn set_noop_allowed(&mut self, allowed: bool) {
+        self.noop_allowed = allowed;
+    }
+
+    /// Whether the tetromino falls on its own. Without gravity it only moves down
+    /// on soft and hard drops.
+    pub fn set_gravity(&mut self, enabled: bool) {
+        self.gravity = enabled;
+    }
+
+    /// Whether `SonicDrop` is offered by `allowed_actions`, so searches can slide or rotate
+    /// a tetromino under an overhang after dropping it
+    pub fn set_sonic_drop_allowed(&mut self, allowed: bool) {
+        self.sonic_drop_allowed = allowed;
+    }
+
+    /// Add a reward for reducing holes and stack height to the reward of each lock,
+    /// which searches also count as score
+    pub fn set_stack_shaping(&mut self, shaping: Option<StackShaping>) {
+        self.stack_shaping = shaping;
+    }
+
+    /// Reward of the last step, for training
+    pub fn last_step_reward(&self) -> f32 {
+        self.rewards
+    }
+
+    /// End the episode once `limit` ticks have passed, instead of playing until game over
+    pub fn set_tick_limit(&mut self, limit: Option<usize>) {
+        self.tick_limit = limit;
+    }
+
+    /// Score and reward by number of lines cleared at once, starting from 0 lines.
+    /// Clears beyond the end of a curve get its last value, and an empty curve is left as is.
+    pub fn set_combo_curves(&mut self, score: Vec<usize>, reward: Vec<f32>) {
+        if !score.is_empty() {
+            self.score_combo = score;
+        }
+        if !reward.is_empty() {
+            self.reward_combo = reward;
+        }
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    /// Sum of the shaped rewards of this episode
+    pub fn ep_return(&self) -> f32 {
+        self.ep_return
+    }
+
+    /// Episode return per tick, to compare episodes of different lengths
+    pub fn normalized_return(&self) -> f32 {
+        if self.tick == 0 {
+            return 0.0;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let ticks = self.tick as f32;
+        self.ep_return / ticks
+    }
+
+    /// Lines cleared this episode
+    pub fn lines_deleted(&self) -> u32 {
+        self.lines_deleted
+    }
+
+    /// Set the score to beat for a new record
+    pub fn set_personal_best(&mut self, best: usize) {
+        self.personal_best = best;
+    }
+
+    /// Whether the last tetromino locked right after a rotation
+    pub fn last_lock_was_spin(&self) -> bool {
+        self.last_lock_shift == Some(Action::Rotate)
+    }
+
+    /// Whether the last tetromino locked right after a sideways move
+    pub fn last_lock_was_tuck(&self) -> bool {
+        matches!(self.last_lock_shift, Some(Action::Left | Action::Right))
+    }
+
+    /// Whether the current score beats the personal best
+    pub fn is_new_record(&self) -> bool {
+        self.score > self.personal_best
+    }
+
+    /// Number of times each tetromino spawned this episode, by id.
+    /// The default 7-bag generator keeps them within one of each other.
+    pub fn deck_statistics(&self) -> [u32; NUM_TETROMINOES] {
+        self.tetromino_counts
+    }
+
+    /// Upcoming tetromino ids after the current one, limited to the preview window
+    pub fn preview(&self, n: usize) -> Vec<usize> {
+        (1..=n.min(NUM_PREVIEW))
+            .map(|i| self.tetromino_deck[(self.cur_position_in_deck + i) % DECK_SIZE])
+            .collect()
     }
 
     // This is only used to check if the game is done
@@ -195,9 +593,10 @@ impl Tetris {
     fn can_spawn_new_tetromino(&self) -> bool {
         let next_pos = (self.cur_position_in_deck + 1) % DECK_SIZE;
         let next_tetromino = self.tetromino_deck[next_pos];
+        let spawn_col = self.spawn_col(next_tetromino);
         for c in 0..(TETROMINO_FILL_COLS[next_tetromino][0] as usize) {
             for r in 0..(TETROMINO_FILL_ROWS[next_tetromino][0] as usize) {
-                if (self.grid[r * self.n_cols + c + self.n_cols / 2] != 0)
+                if (self.grid[r * self.n_cols + c + spawn_col] != 0)
                     && (TETROMINOES[next_tetromino][0][r][c] == 1)
                 {
                     return false;
@@ -272,37 +671,16 @@ impl Tetris {
         true
     }
 
-    #[allow(clippy::needless_range_loop)]
+    /// Whether the current tetromino can be held. The incoming tetromino, the held one or
+    /// else the next one, enters at the spawn position and must fit there.
     fn can_hold(&self) -> bool {
         if !self.can_swap {
             return false;
         }
-        let Some(held) = self.hold_tetromino else {
-            return true;
-        };
-        let held_cols = TETROMINO_FILL_COLS[held][self.cur_tetromino_rot] as usize;
-        let held_rows = TETROMINO_FILL_ROWS[held][self.cur_tetromino_rot] as usize;
-
-        // Check if held piece would fit within bounds at current position
-        if self.cur_tetromino_col + held_cols > self.n_cols {
-            return false;
-        }
-        if self.cur_tetromino_row + held_rows > self.n_rows {
-            return false;
-        }
-
-        for c in 0..held_cols {
-            for r in 0..held_rows {
-                if (self.grid
-                    [(r + self.cur_tetromino_row) * self.n_cols + c + self.cur_tetromino_col]
-                    != 0)
-                    && (TETROMINOES[held][self.cur_tetromino_rot][r][c] == 1)
-                {
-                    return false;
-                }
-            }
+        match self.hold_tetromino {
+            None => self.can_spawn_new_tetromino(),
+            Some(held) => self.fits(held, 0, 0, self.spawn_col(held)),
         }
-        true
     }
 
     #[allow(clippy::needless_range_loop)]
@@ -352,6 +730,125 @@ impl Tetris {
         }
     }
 
+    /// Height of each column, measured from the floor to its highest filled cell
+    pub fn column_heights(&self) -> Vec<usize> {
+        (0..self.n_cols)
+            .map(|c| {
+                (0..self.n_rows)
+                    .find(|&r| self.grid[r * self.n_cols + c] != 0)
+                    .map_or(0, |r| self.n_rows - r)
+            })
+            .collect()
+    }
+
+    /// Number of empty cells below the highest filled cell of each column
+    pub fn column_holes(&self) -> Vec<usize> {
+        (0..self.n_cols)
+            .map(|c| {
+                (0..self.n_rows)
+                    .skip_while(|&r| self.grid[r * self.n_cols + c] == 0)
+                    .filter(|&r| self.grid[r * self.n_cols + c] == 0)
+                    .count()
+            })
+            .collect()
+    }
+
+    pub fn hole_count(&self) -> usize {
+        self.column_holes().iter().sum()
+    }
+
+    pub fn aggregate_height(&self) -> usize {
+        self.column_heights().iter().sum()
+    }
+
+    /// Sum of height differences between adjacent columns
+    pub fn bumpiness(&self) -> usize {
+        self.column_heights()
+            .windows(2)
+            .map(|w| w[0].abs_diff(w[1]))
+            .sum()
+    }
+
+    /// Column and depth of the deepest well, a column lower than both its neighbors.
+    /// The walls count as neighbors as high as the grid. The depth is 0 without wells.
+    pub fn deepest_well(&self) -> (usize, usize) {
+        let heights = self.column_heights();
+        let height = |c: Option<usize>| c.and_then(|c| heights.get(c)).map_or(self.n_rows, |&h| h);
+        (0..self.n_cols)
+            .map(|c| {
+                let lowest_neighbor = height(c.checked_sub(1)).min(height(Some(c + 1)));
+                (c, lowest_neighbor.saturating_sub(heights[c]))
+            })
+            .max_by_key(|&(_, depth)| depth)
+            .unwrap_or((0, 0))
+    }
+
+    /// Number of empty rows above the highest filled cell, 0 once the stack reaches the top
+    pub fn top_out_margin(&self) -> usize {
+        self.n_rows - self.column_heights().into_iter().max().unwrap_or(0)
+    }
+
+    /// Number of filled cells in the locked grid
+    pub fn cells_remaining(&self) -> usize {
+        self.grid.iter().filter(|&&block| block != 0).count()
+    }
+
+    /// Whether at most `threshold` cells are left, so a perfect clear is within reach
+    #[allow(dead_code)]
+    pub fn is_near_perfect_clear(&self, threshold: usize) -> bool {
+        self.cells_remaining() <= threshold
+    }
+
+    /// Text heatmap of the locked grid, annotated with per-column heights and holes.
+    /// Filled cells are drawn as `#`, holes as `o`, and empty cells as `.`.
+    pub fn debug_heatmap(&self) -> String {
+        let heights = self.column_heights();
+        let holes = self.column_holes();
+        let (well_col, well_depth) = self.deepest_well();
+        let last_lock = if self.last_lock_was_spin() {
+            "spin"
+        } else if self.last_lock_was_tuck() {
+            "tuck"
+        } else {
+            "drop"
+        };
+        let mut out = format!(
+            "aggregate height: {}, holes: {}, bumpiness: {}, cells: {}, \
+             deepest well: {well_depth} at column {well_col}, top-out margin: {}, \
+             last lock: {last_lock}\n",
+            self.aggregate_height(),
+            self.hole_count(),
+            self.bumpiness(),
+            self.cells_remaining(),
+            self.top_out_margin()
+        );
+
+        for r in 0..self.n_rows {
+            out.push_str("       |");
+            for (c, height) in heights.iter().enumerate() {
+                let cell = if self.grid[r * self.n_cols + c] != 0 {
+                    '#'
+                } else if r >= self.n_rows - height {
+                    'o'
+                } else {
+                    '.'
+                };
+                let _ = write!(out, "{cell:>3}");
+            }
+            out.push_str(" |\n");
+        }
+
+        out.push_str("height  ");
+        for height in &heights {
+            let _ = write!(out, "{height:>3}");
+        }
+        out.push_str("\nholes   ");
+        for count in &holes {
+            let _ = write!(out, "{count:>3}");
+        }
+        out
+    }
+
     pub fn reset(&mut self) {
         self.score = 0;
         self.hold_tetromino = None;
@@ -369,6 +866,9 @@ impl Tetris {
         self.atn_count_rotate = 0;
         self.atn_count_hold = 0;
         self.tetromino_counts.fill(0);
+        self.cleared_rows.clear();
+        self.last_action = None;
+        self.last_lock_shift = None;
 
         self.restore_grid();
         self.initialize_deck();
@@ -382,6 +882,11 @@ impl Tetris {
             - 1;
         let mut lines_deleted = 0;
         self.can_swap = true;
+        self.last_lock_shift = self.last_shift;
+        self.cleared_rows.clear();
+        let stack_before = self
+            .stack_shaping
+            .map(|_| (self.hole_count(), self.aggregate_height()));
 
         // Fill the main grid with the tetromino
         for c in 0..(TETROMINO_FILL_COLS[self.cur_tetromino][self.cur_tetromino_rot] as usize) {
@@ -389,7 +894,7 @@ impl Tetris {
                 if TETROMINOES[self.cur_tetromino][self.cur_tetromino_rot][r][c] == 1 {
                     self.grid
                         [(r + self.cur_tetromino_row) * self.n_cols + c + self.cur_tetromino_col] =
-                        (self.cur_tetromino + 1) as i32;
+                        cell_id(self.cur_tetromino);
                 }
             }
         }
@@ -397,6 +902,9 @@ impl Tetris {
         // Proceed to delete the complete rows
         for _ in 0..(TETROMINO_FILL_ROWS[self.cur_tetromino][self.cur_tetromino_rot] as usize) {
             if self.is_full_row(row_to_check) {
+                // Rows above move down as rows are cleared
+                self.cleared_rows
+                    .push(row_to_check - lines_deleted as usize);
                 self.clear_row(row_to_check);
                 lines_deleted += 1;
             } else {
@@ -407,14 +915,26 @@ impl Tetris {
         if lines_deleted > 0 {
             self.count_combos += 1;
             self.lines_deleted += lines_deleted;
-            self.score += SCORE_COMBO[lines_deleted as usize] as usize;
-            self.rewards += REWARD_COMBO[lines_deleted as usize];
-            self.ep_return += REWARD_COMBO[lines_deleted as usize];
+            let reward = combo_value(&self.reward_combo, lines_deleted as usize);
+            self.score += combo_value(&self.score_combo, lines_deleted as usize);
+            self.rewards += reward;
+            self.ep_return += reward;
 
             // These determine the game difficulty. Consider making them args.
-            self.game_level = 1 + self.lines_deleted / LINES_PER_LEVEL as u32;
-            self.ticks_per_fall =
-                (INITIAL_TICKS_PER_FALL as i32 - self.game_level as i32 / 4).max(3) as usize;
+            self.game_level = 1 + self.lines_deleted / LINES_PER_LEVEL;
+            self.ticks_per_fall = INITIAL_TICKS_PER_FALL
+                .saturating_sub((self.game_level / 4) as usize)
+                .max(3);
+        }
+
+        if let (Some(shaping), Some((holes, height))) = (self.stack_shaping, stack_before) {
+            #[allow(clippy::cast_precision_loss)]
+            let reward = shaping.reward(
+                self.hole_count() as f32 - holes as f32,
+                self.aggregate_height() as f32 - height as f32,
+            );
+            self.rewards += reward;
+            self.ep_return += reward;
         }
 
         if self.can_spawn_new_tetromino() {
@@ -425,6 +945,7 @@ impl Tetris {
     }
 
     pub fn step(&mut self, action: Action) {
+        self.last_action = Some(action);
         self.is_terminal = false;
         self.rewards = 0.0;
         self.tick += 1;
@@ -434,6 +955,7 @@ impl Tetris {
             Action::Left => {
                 if self.can_go_left() {
                     self.cur_tetromino_col -= 1;
+                    self.last_shift = Some(action);
                 } else {
                     self.rewards += REWARD_INVALID_ACTION;
                     self.ep_return += REWARD_INVALID_ACTION;
@@ -442,6 +964,7 @@ impl Tetris {
             Action::Right => {
                 if self.can_go_right() {
                     self.cur_tetromino_col += 1;
+                    self.last_shift = Some(action);
                 } else {
                     self.rewards += REWARD_INVALID_ACTION;
                     self.ep_return += REWARD_INVALID_ACTION;
@@ -451,6 +974,7 @@ impl Tetris {
                 self.atn_count_rotate += 1;
                 if self.can_rotate() {
                     self.cur_tetromino_rot = (self.cur_tetromino_rot + 1) % NUM_ROTATIONS;
+                    self.last_shift = Some(action);
                     self.rewards += REWARD_ROTATE;
                     self.ep_return += REWARD_ROTATE;
                 } else {
@@ -462,33 +986,40 @@ impl Tetris {
                 self.atn_count_soft_drop += 1;
                 if self.can_soft_drop() {
                     self.cur_tetromino_row += 1;
+                    self.last_shift = None;
                     self.score += SCORE_SOFT_DROP;
                 } else {
                     self.rewards += REWARD_INVALID_ACTION;
                     self.ep_return += REWARD_INVALID_ACTION;
                 }
             }
+            Action::SonicDrop => {
+                self.atn_count_soft_drop += 1;
+                let (row, _) = self.drop_position();
+                if row > self.cur_tetromino_row {
+                    self.score += SCORE_SOFT_DROP * (row - self.cur_tetromino_row);
+                    self.cur_tetromino_row = row;
+                    self.last_shift = None;
+                } else {
+                    self.rewards += REWARD_INVALID_ACTION;
+                    self.ep_return += REWARD_INVALID_ACTION;
+                }
+            }
             Action::Hold => {
                 self.atn_count_hold += 1;
                 if self.can_hold() {
                     let t1 = self.cur_tetromino;
+                    // Both branches leave the incoming tetromino at the spawn position
                     match self.hold_tetromino {
-                        None => {
-                            self.spawn_new_tetromino();
-                            self.hold_tetromino = Some(t1);
-                            self.can_swap = false;
-                        }
+                        None => self.spawn_new_tetromino(),
                         Some(t2) => {
                             self.cur_tetromino = t2;
                             self.tetromino_deck[self.cur_position_in_deck] = t2;
-                            self.hold_tetromino = Some(t1);
-                            self.can_swap = false;
-                            self.cur_tetromino_rot = 0;
-                            self.cur_tetromino_col = self.n_cols / 2;
-                            self.cur_tetromino_row = 0;
-                            self.tick_fall = 0;
+                            self.move_to_spawn();
                         }
                     }
+                    self.hold_tetromino = Some(t1);
+                    self.can_swap = false;
                 } else {
                     self.rewards += REWARD_INVALID_ACTION;
                     self.ep_return += REWARD_INVALID_ACTION;
@@ -496,22 +1027,47 @@ impl Tetris {
             }
             Action::HardDrop => {
                 self.atn_count_hard_drop += 1;
-                while self.can_soft_drop() {
-                    self.cur_tetromino_row += 1;
+                let (row, _) = self.drop_position();
+                for _ in self.cur_tetromino_row..row {
+                    self.last_shift = None;
                     // NOTE: this seems to be a super effective reward trick
                     self.rewards += REWARD_HARD_DROP;
                     self.ep_return += REWARD_HARD_DROP;
                 }
+                self.cur_tetromino_row = row;
                 self.score += SCORE_HARD_DROP;
                 self.place_tetromino();
             }
             Action::NoOp => {} // No operation
         }
 
-        if self.tick_fall >= self.ticks_per_fall {
+        self.apply_gravity();
+        self.check_tick_limit();
+    }
+
+    /// Let one tick pass without player input.
+    /// A real-time loop calls this on a timer and `step` on input.
+    pub fn tick(&mut self) {
+        self.rewards = 0.0;
+        self.tick += 1;
+        self.tick_fall += 1;
+        self.apply_gravity();
+        self.check_tick_limit();
+    }
+
+    fn check_tick_limit(&mut self) {
+        if self.tick_limit.is_some_and(|limit| self.tick >= limit) {
+            self.is_terminal = true;
+        }
+    }
+
+    /// Make the tetromino fall one square, or lock it in place, once enough ticks have passed
+    fn apply_gravity(&mut self) {
+        if self.gravity && self.tick_fall >= self.ticks_per_fall {
             self.tick_fall = 0;
             if self.can_soft_drop() {
                 self.cur_tetromino_row += 1;
+                self.last_shift = None;
             } else {
                 self.place_tetromino();
             }
@@ -521,14 +1077,13 @@ impl Tetris {
     /// Create a render client
     pub fn render_client(&self) -> Client {
         let ui_rows = 1;
-        let deck_rows = SIZE as i32;
-        let total_rows = 1 + ui_rows + 1 + deck_rows + 1 + self.n_rows as i32 + 1;
-        let total_cols = (1 + self.n_cols + 1).max(1 + 3 * NUM_PREVIEW) as i32;
+        let deck_rows = SIZE;
+        let total_rows = 1 + ui_rows + 1 + deck_rows + 1 + self.n_rows + 1;
+        let total_cols = (1 + self.n_cols + 1).max(1 + 3 * NUM_PREVIEW);
 
-        let (rl, thread) = raylib::init()
-            .size(SQUARE_SIZE * total_cols, SQUARE_SIZE * total_rows)
-            .title("Tetris")
-            .build();
+        let (width, height) = cell_position(total_rows, total_cols);
+        let (mut rl, thread) = raylib::init().size(width, height).title("Tetris").build();
+        rl.set_target_fps(60);
 
         Client {
             total_cols,
@@ -540,7 +1095,7 @@ impl Tetris {
         }
     }
 
-    /// Render with the render client
+    /// Render with the render client, flashing the lines cleared since the last call
     pub fn render(&mut self, client: &mut Client) {
         // Ensure we're on the main thread
         let main_thread_id = MAIN_THREAD_ID.get_or_init(|| thread::current().id());
@@ -560,66 +1115,50 @@ impl Tetris {
             client.rl.toggle_fullscreen();
         }
 
+        let cleared_rows = std::mem::take(&mut self.cleared_rows);
+        if !cleared_rows.is_empty() {
+            for frame in 0..LINE_CLEAR_FRAMES {
+                if client.rl.window_should_close() {
+                    return;
+                }
+                let flash = if (frame / 5) % 2 == 0 {
+                    Color::WHITE
+                } else {
+                    Color::GRAY
+                };
+                self.draw_frame(client, &cleared_rows, flash);
+            }
+        }
+        self.draw_frame(client, &[], Color::WHITE);
+    }
+
+    /// Draw one frame, with `cleared_rows` shown in `flash` color where they stood
+    /// before the board collapsed
+    fn draw_frame(&self, client: &mut Client, cleared_rows: &[usize], flash: Color) {
         // Colors
         let border_color = Color::new(100, 100, 100, 255);
         let dash_color = Color::new(80, 80, 80, 255);
         let dash_color_bright = Color::new(150, 150, 150, 255);
         let dash_color_dark = Color::new(50, 50, 50, 255);
 
+        let (deck_top, grid_top) = (client.deck_top(), client.grid_top());
         let mut d = client.rl.begin_drawing(&client.thread);
         d.clear_background(Color::BLACK);
 
         // Draw outer grid border
         for r in 0..client.total_rows {
             for c in 0..client.total_cols {
-                let x = c * SQUARE_SIZE;
-                let y = r * SQUARE_SIZE;
-
                 if (c == 0)
                     || (c == client.total_cols - 1)
-                    || ((r > 1 + client.ui_rows) && (r < 1 + client.ui_rows + 1 + client.deck_rows))
-                    || ((r > 1 + client.ui_rows + client.deck_rows + 1)
-                        && (c >= self.n_rows as i32))
+                    || ((r > 1 + client.ui_rows) && (r < grid_top - 1))
+                    || ((r > grid_top - 1) && (c >= self.n_rows))
                     || (r == 0)
                     || (r == 1 + client.ui_rows)
-                    || (r == 1 + client.ui_rows + 1 + client.deck_rows)
+                    || (r == grid_top - 1)
                     || (r == client.total_rows - 1)
                 {
-                    d.draw_rectangle(
-                        x + HALF_LINEWIDTH,
-                        y + HALF_LINEWIDTH,
-                        SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                        border_color,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        2 * HALF_LINEWIDTH,
-                        dash_color_dark,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y + SQUARE_SIZE - HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        2 * HALF_LINEWIDTH,
-                        dash_color_dark,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        dash_color_dark,
-                    );
-                    d.draw_rectangle(
-                        x + SQUARE_SIZE - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        dash_color_dark,
-                    );
+                    let (x, y) = cell_position(r, c);
+                    draw_cell(&mut d, x, y, border_color, dash_color_dark);
                 }
             }
         }
@@ -627,163 +1166,44 @@ impl Tetris {
         // Draw main grid
         for r in 0..self.n_rows {
             for c in 0..self.n_cols {
-                let x = (c + 1) as i32 * SQUARE_SIZE;
-                let y = (1 + client.ui_rows + 1 + client.deck_rows + 1 + r as i32) * SQUARE_SIZE;
-                let block_id = self.grid[r * self.n_cols + c];
-
-                let color = if block_id == 0 {
-                    Color::BLACK
-                } else if block_id < 0 {
-                    TETROMINO_COLORS[(-block_id - 1) as usize]
+                let (x, y) = cell_position(grid_top + r, c + 1);
+                // Rows above cleared rows were moved down by the collapse
+                let shift = cleared_rows.iter().filter(|&&row| row > r).count();
+                let block_id = self.grid[(r + shift) * self.n_cols + c];
+
+                let color = if cleared_rows.contains(&r) {
+                    flash
                 } else {
-                    TETROMINO_COLORS[(block_id - 1) as usize]
+                    cell_color(block_id)
                 };
 
-                d.draw_rectangle(
-                    x + HALF_LINEWIDTH,
-                    y + HALF_LINEWIDTH,
-                    SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                    SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                    color,
-                );
-                d.draw_rectangle(
-                    x - HALF_LINEWIDTH,
-                    y - HALF_LINEWIDTH,
-                    SQUARE_SIZE,
-                    2 * HALF_LINEWIDTH,
-                    dash_color,
-                );
-                d.draw_rectangle(
-                    x - HALF_LINEWIDTH,
-                    y + SQUARE_SIZE - HALF_LINEWIDTH,
-                    SQUARE_SIZE,
-                    2 * HALF_LINEWIDTH,
-                    dash_color,
-                );
-                d.draw_rectangle(
-                    x - HALF_LINEWIDTH,
-                    y - HALF_LINEWIDTH,
-                    2 * HALF_LINEWIDTH,
-                    SQUARE_SIZE,
-                    dash_color,
-                );
-                d.draw_rectangle(
-                    x + SQUARE_SIZE - HALF_LINEWIDTH,
-                    y - HALF_LINEWIDTH,
-                    2 * HALF_LINEWIDTH,
-                    SQUARE_SIZE,
-                    dash_color,
-                );
+                draw_cell(&mut d, x, y, color, dash_color);
             }
         }
 
         // Draw current tetromino
-        for r in 0..SIZE {
-            for c in 0..SIZE {
-                if TETROMINOES[self.cur_tetromino][self.cur_tetromino_rot][r][c] == 1 {
-                    let x = (c + self.cur_tetromino_col + 1) as i32 * SQUARE_SIZE;
-                    let y = (1
-                        + client.ui_rows
-                        + 1
-                        + client.deck_rows
-                        + 1
-                        + r as i32
-                        + self.cur_tetromino_row as i32)
-                        * SQUARE_SIZE;
+        let shape = &TETROMINOES[self.cur_tetromino][self.cur_tetromino_rot];
+        for (r, row) in shape.iter().enumerate() {
+            for (c, &filled) in row.iter().enumerate() {
+                if filled == 1 {
+                    let (x, y) = cell_position(
+                        grid_top + r + self.cur_tetromino_row,
+                        c + self.cur_tetromino_col + 1,
+                    );
                     let color = TETROMINO_COLORS[self.cur_tetromino];
 
-                    d.draw_rectangle(
-                        x + HALF_LINEWIDTH,
-                        y + HALF_LINEWIDTH,
-                        SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                        color,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        2 * HALF_LINEWIDTH,
-                        dash_color,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y + SQUARE_SIZE - HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        2 * HALF_LINEWIDTH,
-                        dash_color,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        dash_color,
-                    );
-                    d.draw_rectangle(
-                        x + SQUARE_SIZE - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        dash_color,
-                    );
+                    draw_cell(&mut d, x, y, color, dash_color);
                 }
             }
         }
 
         // Draw deck preview (next pieces)
-        for i in 0..NUM_PREVIEW {
-            let deck_idx = (self.cur_position_in_deck + 1 + i) % DECK_SIZE;
-            let tetromino_id = self.tetromino_deck[deck_idx];
+        for (i, tetromino_id) in self.preview(NUM_PREVIEW).into_iter().enumerate() {
             for r in 0..SIZE {
                 for c in 0..2 {
-                    let x = (c + 1 + 3 * i) as i32 * SQUARE_SIZE;
-                    let y = (1 + client.ui_rows + 1 + r as i32) * SQUARE_SIZE;
-                    let r_offset = SIZE - TETROMINO_FILL_ROWS[tetromino_id][0] as usize;
-
-                    let color = if r < r_offset {
-                        Color::BLACK
-                    } else if TETROMINOES[tetromino_id][0][r - r_offset][c] == 0 {
-                        Color::BLACK
-                    } else {
-                        TETROMINO_COLORS[tetromino_id]
-                    };
-
-                    d.draw_rectangle(
-                        x + HALF_LINEWIDTH,
-                        y + HALF_LINEWIDTH,
-                        SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                        color,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        2 * HALF_LINEWIDTH,
-                        dash_color_bright,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y + SQUARE_SIZE - HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        2 * HALF_LINEWIDTH,
-                        dash_color_bright,
-                    );
-                    d.draw_rectangle(
-                        x - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        dash_color_bright,
-                    );
-                    d.draw_rectangle(
-                        x + SQUARE_SIZE - HALF_LINEWIDTH,
-                        y - HALF_LINEWIDTH,
-                        2 * HALF_LINEWIDTH,
-                        SQUARE_SIZE,
-                        dash_color_bright,
-                    );
+                    let (x, y) = cell_position(deck_top + r, c + 1 + 3 * i);
+                    let color = boxed_color(tetromino_id, r, c);
+                    draw_cell(&mut d, x, y, color, dash_color_bright);
                 }
             }
         }
@@ -791,55 +1211,11 @@ impl Tetris {
         // Draw hold tetromino
         for r in 0..SIZE {
             for c in 0..2 {
-                let x = (client.total_cols - 3 + c as i32) * SQUARE_SIZE;
-                let y = (1 + client.ui_rows + 1 + r as i32) * SQUARE_SIZE;
-
-                let color = if let Some(hold_id) = self.hold_tetromino {
-                    let r_offset = SIZE - TETROMINO_FILL_ROWS[hold_id][0] as usize;
-                    if r < r_offset || TETROMINOES[hold_id][0][r - r_offset][c] == 0 {
-                        Color::BLACK
-                    } else {
-                        TETROMINO_COLORS[hold_id]
-                    }
-                } else {
-                    Color::BLACK
-                };
-
-                d.draw_rectangle(
-                    x + HALF_LINEWIDTH,
-                    y + HALF_LINEWIDTH,
-                    SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                    SQUARE_SIZE - 2 * HALF_LINEWIDTH,
-                    color,
-                );
-                d.draw_rectangle(
-                    x - HALF_LINEWIDTH,
-                    y - HALF_LINEWIDTH,
-                    SQUARE_SIZE,
-                    2 * HALF_LINEWIDTH,
-                    dash_color_bright,
-                );
-                d.draw_rectangle(
-                    x - HALF_LINEWIDTH,
-                    y + SQUARE_SIZE - HALF_LINEWIDTH,
-                    SQUARE_SIZE,
-                    2 * HALF_LINEWIDTH,
-                    dash_color_bright,
-                );
-                d.draw_rectangle(
-                    x - HALF_LINEWIDTH,
-                    y - HALF_LINEWIDTH,
-                    2 * HALF_LINEWIDTH,
-                    SQUARE_SIZE,
-                    dash_color_bright,
-                );
-                d.draw_rectangle(
-                    x + SQUARE_SIZE - HALF_LINEWIDTH,
-                    y - HALF_LINEWIDTH,
-                    2 * HALF_LINEWIDTH,
-                    SQUARE_SIZE,
-                    dash_color_bright,
-                );
+                let (x, y) = cell_position(deck_top + r, client.total_cols - 3 + c);
+                let color = self
+                    .hold_tetromino
+                    .map_or(Color::BLACK, |hold_id| boxed_color(hold_id, r, c));
+                draw_cell(&mut d, x, y, color, dash_color_bright);
             }
         }
 
@@ -853,11 +1229,120 @@ impl Tetris {
         );
         d.draw_text(
             &format!("Lvl: {}", self.game_level),
-            (client.total_cols - 4) * SQUARE_SIZE,
+            cell_position(0, client.total_cols - 4).0,
             SQUARE_SIZE + 4,
             28,
             Color::new(160, 255, 160, 255),
         );
+        if self.is_new_record() {
+            d.draw_text(
+                "NEW RECORD!",
+                SQUARE_SIZE + 4,
+                4,
+                24,
+                Color::new(255, 215, 0, 255),
+            );
+        }
+    }
+
+    /// Play with the keyboard until game over: left and right move, repeating when held,
+    /// up rotates, down soft drops, space hard drops and C holds
+    pub fn play(&mut self, client: &mut Client, mut shift: AutoShift) {
+        let mut frame: u32 = 0;
+        while !self.is_terminal
+            && !client.rl.window_should_close()
+            && !client.rl.is_key_down(KeyboardKey::KEY_ESCAPE)
+        {
+            let held = if client.rl.is_key_down(KeyboardKey::KEY_LEFT) {
+                Some(Action::Left)
+            } else if client.rl.is_key_down(KeyboardKey::KEY_RIGHT) {
+                Some(Action::Right)
+            } else {
+                None
+            };
+            let action = shift.update(held).or_else(|| {
+                [
+                    (KeyboardKey::KEY_UP, Action::Rotate),
+                    (KeyboardKey::KEY_DOWN, Action::SoftDrop),
+                    (KeyboardKey::KEY_SPACE, Action::HardDrop),
+                    (KeyboardKey::KEY_C, Action::Hold),
+                ]
+                .into_iter()
+                .find(|&(key, _)| client.rl.is_key_pressed(key))
+                .map(|(_, action)| action)
+            });
+
+            match action {
+                Some(action) => self.step(action),
+                None if frame.is_multiple_of(GRAVITY_FRAMES) => self.tick(),
+                None => {}
+            }
+            self.render(client);
+            frame = frame.wrapping_add(1);
+        }
+    }
+}
+
+/// Fields that determine how a game continues.
+/// Telemetry such as action counts and returns doesn't take part in equality.
+#[derive(PartialEq, Eq, Hash)]
+struct Position<'a> {
+    grid: &'a [i32; GRID_SIZE],
+    tetromino_deck: &'a [usize; DECK_SIZE],
+    cur_position_in_deck: usize,
+    hold_tetromino: Option<usize>,
+    cur_tetromino: (usize, usize, usize, usize),
+    tick_fall: usize,
+    ticks_per_fall: usize,
+    can_swap: bool,
+    is_terminal: bool,
+    score: usize,
+    lines_deleted: u32,
+}
+
+impl Tetris {
+    fn position(&self) -> Position<'_> {
+        Position {
+            grid: &self.grid,
+            tetromino_deck: &self.tetromino_deck,
+            cur_position_in_deck: self.cur_position_in_deck,
+            hold_tetromino: self.hold_tetromino,
+            cur_tetromino: (
+                self.cur_tetromino,
+                self.cur_tetromino_row,
+                self.cur_tetromino_col,
+                self.cur_tetromino_rot,
+            ),
+            tick_fall: self.tick_fall,
+            ticks_per_fall: self.ticks_per_fall,
+            can_swap: self.can_swap,
+            is_terminal: self.is_terminal,
+            score: self.score,
+            lines_deleted: self.lines_deleted,
+        }
+    }
+}
+
+/// Value of a combo curve for `lines` cleared at once, clamped to the last entry
+fn combo_value<T: Copy + Default>(curve: &[T], lines: usize) -> T {
+    curve
+        .get(lines)
+        .or(curve.last())
+        .copied()
+        .unwrap_or_default()
+}
+
+impl PartialEq for Tetris {
+    fn eq(&self, other: &Self) -> bool {
+        self.position() == other.position()
+    }
+}
+
+impl Eq for Tetris {}
+
+impl Hash for Tetris {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.position().hash(state);
     }
 }
 
@@ -865,9 +1350,8 @@ const NUM_TETROMINOES: usize = 7;
 const NUM_ROTATIONS: usize = 4;
 const SIZE: usize = 4;
 
-#[allow(dead_code)]
-const TETROMINO_COLORS: [Color; 8] = [
-    Color::new(255, 255, 0, 255), // Yellow
+/// Color of each tetromino, by id. Grid cells store the id plus one.
+const TETROMINO_COLORS: [Color; NUM_TETROMINOES] = [
     Color::new(255, 255, 0, 255), // Yellow
     Color::new(0, 255, 255, 255), // Cyan
     Color::new(0, 255, 0, 255),   // Green
@@ -877,6 +1361,31 @@ const TETROMINO_COLORS: [Color; 8] = [
     Color::new(0, 0, 255, 255),   // Blue
 ];
 
+/// Grid value of a cell locked by `tetromino`, its id plus one
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+fn cell_id(tetromino: usize) -> i32 {
+    (tetromino + 1) as i32
+}
+
+/// Color of a locked grid cell, which stores the tetromino id plus one, or 0 when empty
+fn cell_color(block_id: i32) -> Color {
+    match block_id.unsigned_abs() as usize {
+        0 => Color::BLACK,
+        id => TETROMINO_COLORS[id - 1],
+    }
+}
+
+/// Color of the cell at (`r`, `c`) of a `SIZE`-row box showing the unrotated `tetromino`
+/// resting on its bottom
+fn boxed_color(tetromino: usize, r: usize, c: usize) -> Color {
+    let r_offset = SIZE - TETROMINO_FILL_ROWS[tetromino][0] as usize;
+    if r >= r_offset && TETROMINOES[tetromino][0][r - r_offset][c] == 1 {
+        TETROMINO_COLORS[tetromino]
+    } else {
+        Color::BLACK
+    }
+}
+
 const TETROMINOES: [[[[u8; SIZE]; SIZE]; NUM_ROTATIONS]; NUM_TETROMINOES] = [
     [
         [[1, 1, 0, 0], [1, 1, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]],
@@ -949,20 +1458,58 @@ impl Game for Tetris {
         println!("Watch it go...");
     }
 
+    /// Re-draws the upcoming tetrominoes beyond the preview window,
+    /// as the generator conceals them within each batch
+    fn observable_clone(&self, _observer: Player, rng: &mut fastrand::Rng) -> Self {
+        let mut game = self.clone();
+        game.rng = rand::rngs::SmallRng::seed_from_u64(rng.u64(..));
+
+        // Pieces up to the end of the next batch are already drawn, the rest get drawn later
+        let pos = game.cur_position_in_deck;
+        let hidden: Vec<usize> = (NUM_PREVIEW + 1..DECK_SIZE - pos % NUM_TETROMINOES)
+            .map(|d| (pos + d) % DECK_SIZE)
+            .collect();
+        for batch in [0, 1] {
+            let idxs: Vec<usize> = hidden
+                .iter()
+                .copied()
+                .filter(|i| i / NUM_TETROMINOES == batch)
+                .collect();
+            let mut pieces: Vec<usize> = idxs.iter().map(|&i| game.tetromino_deck[i]).collect();
+            game.generator.conceal(&mut pieces, &mut game.rng);
+            for (i, piece) in idxs.into_iter().zip(pieces) {
+                game.tetromino_deck[i] = piece;
+            }
+        }
+        game
+    }
+
+    /// Episode return, including the stack shaping if on
     fn current_reward(&self) -> f64 {
-        self.score as f64
+        f64::from(self.ep_return)
+    }
+
+    /// Random rollouts return 1 to 3 from drops and rotations, a single line adding 0.1.
+    /// Stack shaping can cost as much as it earns.
+    fn reward_range(&self) -> (f64, f64) {
+        if self.stack_shaping.is_some() {
+            (-ROLLOUT_RETURN, ROLLOUT_RETURN)
+        } else {
+            (0.0, ROLLOUT_RETURN)
+        }
     }
 
+    /// The episode return once the game is over or the tick limit is reached
     fn result(&self) -> Option<GameResult> {
         if self.is_terminal {
-            Some(GameResult::End(self.score as f64))
+            Some(GameResult::End(self.current_reward()))
         } else {
             None
         }
     }
 
     fn allowed_actions(&self) -> Vec<super::Action> {
-        let mut actions = Vec::with_capacity(7);
+        let mut actions = Vec::with_capacity(NUM_ACTIONS);
         actions.push(Action::NoOp as usize);
         if self.can_go_left() {
             actions.push(Action::Left as usize);
@@ -976,10 +1523,16 @@ impl Game for Tetris {
         if self.can_soft_drop() {
             actions.push(Action::SoftDrop as usize);
             actions.push(Action::HardDrop as usize);
+            if self.sonic_drop_allowed {
+                actions.push(Action::SonicDrop as usize);
+            }
         }
         if self.can_hold() {
             actions.push(Action::Hold as usize);
         }
+        if !self.noop_allowed && actions.len() > 1 {
+            actions.remove(0);
+        }
         actions
     }
 
@@ -987,9 +1540,565 @@ impl Game for Tetris {
         Player::X
     }
 
+    fn num_players(&self) -> usize {
+        1
+    }
+
+    fn next_player(&self) -> Player {
+        Player::X
+    }
+
+    fn action_name(&self, action: super::Action) -> String {
+        format!(
+            "{:?}",
+            u8::try_from(action).map_or(Action::NoOp, Action::from)
+        )
+    }
+
+    fn action_from_name(&self, name: &str) -> Result<super::Action, &'static str> {
+        let name = name.trim();
+        (0..NUM_ACTIONS)
+            .find(|&action| self.action_name(action) == name)
+            .ok_or("Unknown action")
+    }
+
     fn step(&mut self, action: super::Action) -> Result<(), &'static str> {
-        let action = Action::from(action as u8);
+        let action = u8::try_from(action).map_or(Action::NoOp, Action::from);
         self.step(action);
         Ok(())
     }
+
+    /// Shape `[2, rows, cols]`: planes for locked cells and the falling tetromino
+    #[allow(clippy::needless_range_loop)]
+    fn to_tensor(&self) -> (Vec<f32>, Vec<usize>) {
+        let size = self.n_rows * self.n_cols;
+        let mut tensor = vec![0.0; 2 * size];
+        for (i, &block) in self.grid.iter().enumerate() {
+            if block != 0 {
+                tensor[i] = 1.0;
+            }
+        }
+        for r in 0..SIZE {
+            for c in 0..SIZE {
+                if TETROMINOES[self.cur_tetromino][self.cur_tetromino_rot][r][c] == 1 {
+                    let row = self.cur_tetromino_row + r;
+                    let col = self.cur_tetromino_col + c;
+                    tensor[size + row * self.n_cols + col] = 1.0;
+                }
+            }
+        }
+        (tensor, vec![2, self.n_rows, self.n_cols])
+    }
+
+    fn action_space_size(&self) -> usize {
+        NUM_ACTIONS
+    }
+
+    fn ply(&self) -> usize {
+        self.tick
+    }
+
+    fn last_action(&self) -> Option<super::Action> {
+        self.last_action.map(|action| action as super::Action)
+    }
+
+    /// Filled cells of the single player's grid
+    fn material(&self) -> Vec<usize> {
+        vec![self.cells_remaining()]
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        if self
+            .grid
+            .iter()
+            .any(|cell| cell.unsigned_abs() as usize > NUM_TETROMINOES)
+        {
+            return Err("Grid cell holds an unknown tetromino");
+        }
+        Ok(())
+    }
+
+    /// Grid rows from the top as # and ., then the current tetromino with its rotation, row
+    /// and column, then the held tetromino
+    fn state_key(&self) -> String {
+        let grid: String = self
+            .grid
+            .iter()
+            .map(|&cell| if cell == 0 { '.' } else { '#' })
+            .collect();
+        let hold = self
+            .hold_tetromino
+            .map_or_else(|| "-".to_string(), |held| held.to_string());
+        format!(
+            "{grid}|{}{}@{},{}|{hold}",
+            self.cur_tetromino,
+            self.cur_tetromino_rot,
+            self.cur_tetromino_row,
+            self.cur_tetromino_col
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A game without gravity whose grid ends with the `bottom` rows, with `piece` falling
+    fn game_on(bottom: &[&str], piece: usize) -> Tetris {
+        let mut grid = "..........\n".repeat(NUM_ROWS - bottom.len());
+        for row in bottom {
+            grid.push_str(row);
+            grid.push('\n');
+        }
+        let mut game = Tetris::from_grid_str(&grid, piece, None).unwrap();
+        game.set_gravity(false);
+        game
+    }
+
+    /// Move the falling tetromino so its left column is at `col`, then hard drop it
+    fn hard_drop_at(game: &mut Tetris, col: usize) {
+        while game.cur_tetromino_col > col {
+            Game::step(game, Action::Left as usize).unwrap();
+        }
+        while game.cur_tetromino_col < col {
+            Game::step(game, Action::Right as usize).unwrap();
+        }
+        Game::step(game, Action::HardDrop as usize).unwrap();
+    }
+
+    /// The O tetromino dropped at `col` next to a single filled cell in column 0
+    fn drop_square(col: usize, shaping: Option<StackShaping>) -> Tetris {
+        let mut game = game_on(&["#........."], 0);
+        game.set_stack_shaping(shaping);
+        hard_drop_at(&mut game, col);
+        game
+    }
+
+    #[test]
+    fn shaping_prefers_clean_placements() {
+        let shaping = Some(StackShaping {
+            hole_weight: 1.0,
+            height_weight: 0.0,
+        });
+        let clean = drop_square(2, shaping);
+        let holed = drop_square(0, shaping);
+        assert_eq!((clean.hole_count(), holed.hole_count()), (0, 1));
+        assert!(clean.last_step_reward() > holed.last_step_reward());
+        assert!(clean.current_reward() > holed.current_reward());
+
+        let unshaped = drop_square(0, None);
+        assert_eq!(holed.score(), unshaped.score());
+        assert!(holed.current_reward() < unshaped.current_reward());
+    }
+
+    #[test]
+    fn clears_beyond_the_combo_curves_get_their_last_value() {
+        let well = "#########.";
+        let tetris = |score: Vec<usize>, reward: Vec<f32>| {
+            let mut game = game_on(&[well; 4], 1);
+            game.set_combo_curves(score, reward);
+            hard_drop_at(&mut game, 9);
+            game
+        };
+        let default = tetris(vec![], vec![]);
+        let short = tetris(vec![0, 100], vec![0.0, 0.1]);
+        assert_eq!((default.lines_deleted(), short.lines_deleted()), (4, 4));
+        assert_eq!(default.score() - short.score(), 900);
+        assert!((default.ep_return() - short.ep_return() - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn locks_after_a_rotation_or_sideways_move_are_spins_or_tucks() {
+        // The T tetromino rests upright over the gap, then rotates onto the cells beside it
+        let mut spin = game_on(&["#.#......."], 4);
+        while spin.cur_tetromino_col > 0 {
+            spin.step(Action::Left);
+        }
+        spin.step(Action::SonicDrop);
+        spin.step(Action::Rotate);
+        spin.step(Action::HardDrop);
+        assert!(spin.last_lock_was_spin() && !spin.last_lock_was_tuck());
+
+        let mut tuck = game_on(&[], 0);
+        tuck.step(Action::SonicDrop);
+        tuck.step(Action::Left);
+        tuck.step(Action::HardDrop);
+        assert!(tuck.last_lock_was_tuck() && !tuck.last_lock_was_spin());
+
+        let mut drop = game_on(&[], 0);
+        drop.step(Action::Left);
+        drop.step(Action::HardDrop);
+        assert!(!drop.last_lock_was_tuck() && !drop.last_lock_was_spin());
+    }
+
+    #[test]
+    fn cells_remaining_counts_filled_cells() {
+        assert_eq!(game_on(&[], 0).cells_remaining(), 0);
+        assert_eq!(
+            game_on(&["#.........", "##.#......"], 0).cells_remaining(),
+            4
+        );
+    }
+
+    #[test]
+    fn heatmap_reports_column_heights() {
+        let heatmap = game_on(&["..#......."], 0).debug_heatmap();
+        let heights: Vec<&str> = heatmap
+            .lines()
+            .find_map(|line| line.strip_prefix("height"))
+            .unwrap()
+            .split_whitespace()
+            .collect();
+        assert_eq!(heights, ["0", "0", "1", "0", "0", "0", "0", "0", "0", "0"]);
+        assert!(heatmap.starts_with("aggregate height: 1, holes: 0,"));
+    }
+
+    #[test]
+    fn dropping_into_the_gap_of_a_loaded_row_clears_it() {
+        let mut game = game_on(&["#########."], 1);
+        hard_drop_at(&mut game, 9);
+        assert_eq!(game.lines_deleted(), 1);
+        assert_eq!(game.column_heights(), [0, 0, 0, 0, 0, 0, 0, 0, 0, 3]);
+    }
+
+    #[test]
+    fn ticks_alone_lock_the_tetromino() {
+        let mut game = Tetris::with_seed(1);
+        let (row, _) = game.drop_position();
+        let mut ticks = 0;
+        while game.cells_remaining() == 0 {
+            game.tick();
+            ticks += 1;
+            assert!(
+                ticks <= (NUM_ROWS + 1) * INITIAL_TICKS_PER_FALL,
+                "never locked"
+            );
+        }
+        assert_eq!(game.cells_remaining(), 4);
+        assert_eq!(game.top_out_margin(), row);
+    }
+
+    #[test]
+    fn preview_lists_the_next_spawns() {
+        let mut game = Tetris::with_seed(3);
+        let preview = game.preview(usize::MAX);
+        assert_eq!(preview.len(), NUM_PREVIEW);
+        for expected in preview {
+            game.spawn_new_tetromino();
+            assert_eq!(game.cur_tetromino, expected);
+        }
+    }
+
+    #[test]
+    fn observable_clones_keep_the_visible_pieces() {
+        let game = Tetris::with_seed(11);
+        let mut rng = fastrand::Rng::with_seed(0);
+        let mut resampled = false;
+        for _ in 0..20 {
+            let clone = game.observable_clone(Player::X, &mut rng);
+            assert_eq!(clone.cur_tetromino, game.cur_tetromino);
+            assert_eq!(clone.preview(NUM_PREVIEW), game.preview(NUM_PREVIEW));
+            for batch in [0..NUM_TETROMINOES, NUM_TETROMINOES..DECK_SIZE] {
+                let mut pieces = clone.tetromino_deck[batch.clone()].to_vec();
+                let mut original = game.tetromino_deck[batch].to_vec();
+                pieces.sort_unstable();
+                original.sort_unstable();
+                assert_eq!(pieces, original);
+            }
+            resampled |= clone.tetromino_deck != game.tetromino_deck;
+        }
+        assert!(resampled, "the hidden pieces are never re-drawn");
+    }
+
+    #[test]
+    fn noop_can_be_left_out_of_the_actions() {
+        let mut game = Tetris::with_seed(2);
+        let with_noop = game.allowed_actions();
+        assert_eq!(with_noop[0], Action::NoOp as usize);
+        game.set_noop_allowed(false);
+        let without = game.allowed_actions();
+        assert_eq!(without, with_noop[1..]);
+    }
+
+    #[test]
+    fn only_scores_above_the_personal_best_are_records() {
+        let mut game = game_on(&["#########."; 4], 1);
+        game.set_personal_best(0);
+        assert!(!game.is_new_record());
+        hard_drop_at(&mut game, 9);
+        let score = game.score();
+        assert!(score > 0);
+        assert!(game.is_new_record());
+        game.set_personal_best(score);
+        assert!(!game.is_new_record());
+        game.set_personal_best(score - 1);
+        assert!(game.is_new_record());
+    }
+
+    #[test]
+    fn cleared_rows_are_where_the_lines_stood() {
+        let mut game = game_on(&["#########.", "########..", "#########.", "#########."], 1);
+        hard_drop_at(&mut game, 9);
+        assert_eq!(game.lines_deleted(), 3);
+        assert_eq!(
+            game.cleared_rows,
+            [NUM_ROWS - 1, NUM_ROWS - 2, NUM_ROWS - 4]
+        );
+    }
+
+    #[test]
+    fn tick_limits_end_the_episode_with_its_return() {
+        let mut game = Tetris::with_seed(4);
+        game.set_tick_limit(Some(5));
+        let mut steps = 0;
+        while game.result().is_none() {
+            Game::step(&mut game, Action::Rotate as usize).unwrap();
+            steps += 1;
+        }
+        assert_eq!((steps, game.tick), (5, 5));
+        assert!(game.ep_return() > 0.0);
+        assert_eq!(
+            game.result(),
+            Some(GameResult::End(f64::from(game.ep_return())))
+        );
+    }
+
+    #[test]
+    fn auto_shift_repeats_after_the_delay() {
+        let mut shift = AutoShift::new(3, 2);
+        let moves: Vec<bool> = (0..8)
+            .map(|_| shift.update(Some(Action::Left)) == Some(Action::Left))
+            .collect();
+        assert_eq!(moves, [true, false, false, true, false, true, false, true]);
+        assert_eq!(shift.update(None), None);
+        assert_eq!(shift.update(Some(Action::Right)), Some(Action::Right));
+        assert_eq!(shift.update(Some(Action::Right)), None);
+    }
+
+    #[test]
+    fn last_action_names_the_tetris_move() {
+        let mut game = Tetris::with_seed(5);
+        assert_eq!(Game::last_action(&game), None);
+        Game::step(&mut game, Action::Left as usize).unwrap();
+        assert_eq!(Game::last_action(&game), Some(Action::Left as usize));
+        assert_eq!(game.action_name(Action::Left as usize), "Left");
+    }
+
+    #[test]
+    fn holds_need_room_at_the_spawn_position() {
+        for held in [None, Some(1)] {
+            let mut game = game_on(&[], 0);
+            game.hold_tetromino = held;
+            game.cur_tetromino_row = 10;
+            assert!(game.can_hold(), "holding {held:?}");
+            // The falling piece has room further down, but nothing can enter at the top
+            game.grid[..2 * NUM_COLS].fill(1);
+            assert!(!game.can_hold(), "holding {held:?}");
+            assert!(!game.allowed_actions().contains(&(Action::Hold as usize)));
+        }
+    }
+
+    #[test]
+    fn near_perfect_clears_leave_few_cells() {
+        let game = game_on(&["#.........", "##.#......"], 0);
+        assert!(game.is_near_perfect_clear(4));
+        assert!(!game.is_near_perfect_clear(3));
+        assert!(game_on(&[], 0).is_near_perfect_clear(0));
+    }
+
+    #[test]
+    fn action_names_parse_back() {
+        let game = Tetris::with_seed(0);
+        for action in 0..NUM_ACTIONS {
+            assert_eq!(game.action_from_name(&game.action_name(action)), Ok(action));
+        }
+        assert_eq!(
+            game.action_from_name(" HardDrop "),
+            Ok(Action::HardDrop as usize)
+        );
+        assert!(game.action_from_name("hard drop").is_err());
+    }
+
+    #[test]
+    fn drop_position_rests_on_the_stack() {
+        let mut game = game_on(&["#........."], 0);
+        let spawn_col = game.cur_tetromino_col;
+        assert_eq!(game.drop_position(), (NUM_ROWS - 2, spawn_col));
+        while game.cur_tetromino_col > 0 {
+            Game::step(&mut game, Action::Left as usize).unwrap();
+        }
+        assert_eq!(game.drop_position(), (NUM_ROWS - 3, 0));
+        Game::step(&mut game, Action::HardDrop as usize).unwrap();
+        assert_eq!(game.column_heights()[..2], [3, 3]);
+    }
+
+    #[test]
+    fn material_is_the_locked_cells() {
+        let game = game_on(&["#.........", "##.#......"], 0);
+        assert_eq!(game.material(), [4]);
+    }
+
+    #[test]
+    fn bags_spawn_every_tetromino_equally() {
+        let mut game = Tetris::with_seed(6);
+        assert_eq!(game.deck_statistics().iter().sum::<u32>(), 1);
+        for _ in 1..10 * NUM_TETROMINOES {
+            game.spawn_new_tetromino();
+        }
+        assert_eq!(game.deck_statistics(), [10; NUM_TETROMINOES]);
+    }
+
+    #[test]
+    fn noops_leave_the_tetromino_in_place_without_gravity() {
+        let mut game = Tetris::with_seed(6);
+        game.set_gravity(false);
+        let start = (game.cur_tetromino_row, game.cur_tetromino_col);
+        for _ in 0..10 * INITIAL_TICKS_PER_FALL {
+            Game::step(&mut game, Action::NoOp as usize).unwrap();
+        }
+        assert_eq!((game.cur_tetromino_row, game.cur_tetromino_col), start);
+
+        game.set_gravity(true);
+        for _ in 0..=INITIAL_TICKS_PER_FALL {
+            Game::step(&mut game, Action::NoOp as usize).unwrap();
+        }
+        assert!(game.cur_tetromino_row > start.0);
+    }
+
+    #[test]
+    fn sonic_drops_land_on_the_drop_position_without_locking() {
+        let mut game = game_on(&["#.........", "##........"], 0);
+        game.set_sonic_drop_allowed(true);
+        assert!(Game::allowed_actions(&game).contains(&(Action::SonicDrop as usize)));
+        let landing = game.drop_position();
+        let tetromino = game.cur_tetromino;
+        game.step(Action::SonicDrop);
+        assert_eq!((game.cur_tetromino_row, game.cur_tetromino_col), landing);
+        assert_eq!(game.cur_tetromino, tetromino);
+        assert_eq!(game.cells_remaining(), 3);
+        assert!(!Game::allowed_actions(&game).contains(&(Action::SonicDrop as usize)));
+        assert!(Game::allowed_actions(&game).contains(&(Action::Right as usize)));
+    }
+
+    #[test]
+    fn deepest_well_finds_the_lowest_column_between_higher_ones() {
+        assert_eq!(game_on(&[], 0).deepest_well().1, 0);
+        let middle = game_on(&["..........", "####.#####", "####.#####"], 0);
+        assert_eq!(middle.deepest_well(), (4, 2));
+        let edge = game_on(&[".#########", ".#########", ".####.####"], 0);
+        assert_eq!(edge.deepest_well(), (0, 3));
+    }
+
+    #[test]
+    fn loaded_tetrominoes_spawn_centered() {
+        for (piece, fill_cols) in TETROMINO_FILL_COLS.iter().enumerate() {
+            let game = game_on(&[], piece);
+            let width = fill_cols[0] as usize;
+            assert_eq!(game.cur_tetromino_row, 0);
+            assert_eq!(
+                game.cur_tetromino_col,
+                (NUM_COLS - width) / 2,
+                "piece {piece}"
+            );
+            assert!(game.result().is_none());
+        }
+    }
+
+    #[test]
+    fn blocked_spawns_load_as_game_over() {
+        let mut grid = "....#.....\n".to_string();
+        grid.push_str(&"..........\n".repeat(NUM_ROWS - 1));
+        let game = Tetris::from_grid_str(&grid, 1, None).unwrap();
+        assert!(matches!(game.result(), Some(GameResult::End(_))));
+    }
+
+    #[test]
+    fn normalized_return_is_the_return_per_tick() {
+        let mut game = game_on(&["#########."], 1);
+        assert!(game.normalized_return().abs() < f32::EPSILON);
+        hard_drop_at(&mut game, 9);
+        assert_eq!(game.lines_deleted(), 1);
+        #[allow(clippy::cast_precision_loss)]
+        let per_tick = game.ep_return() / game.tick as f32;
+        assert!(game.normalized_return() > 0.0);
+        assert!((game.normalized_return() - per_tick).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn state_keys_encode_the_grid_and_pieces() {
+        let game = game_on(&["#........."], 1);
+        let grid = format!("{}#.........", ".".repeat((NUM_ROWS - 1) * NUM_COLS));
+        let col = game.cur_tetromino_col;
+        assert_eq!(Game::state_key(&game), format!("{grid}|10@0,{col}|-"));
+
+        let mut moved = game.clone();
+        moved.step(Action::Right);
+        assert_eq!(
+            Game::state_key(&moved),
+            format!("{grid}|10@0,{}|-", col + 1)
+        );
+    }
+
+    #[test]
+    fn locked_cells_keep_the_color_of_their_tetromino() {
+        let rgba = |color: Color| (color.r, color.g, color.b, color.a);
+        for (piece, &color) in TETROMINO_COLORS.iter().enumerate() {
+            let mut game = game_on(&[], piece);
+            Game::step(&mut game, Action::HardDrop as usize).unwrap();
+            let locked: Vec<i32> = game.grid.iter().copied().filter(|&id| id != 0).collect();
+            assert_eq!(locked.len(), 4);
+            assert!(locked.iter().all(|&id| rgba(cell_color(id)) == rgba(color)));
+        }
+        assert_eq!(rgba(cell_color(0)), rgba(Color::BLACK));
+        let mut distinct: Vec<_> = TETROMINO_COLORS.iter().map(|&color| rgba(color)).collect();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), NUM_TETROMINOES);
+    }
+
+    #[test]
+    fn line_clears_return_a_positive_reward() {
+        let mut game = game_on(&["#########."], 1);
+        while game.cur_tetromino_col < 9 {
+            game.step(Action::Right);
+        }
+        let before = game.ep_return();
+        let reward = game
+            .step_returning_reward(Action::HardDrop as usize)
+            .unwrap();
+        assert_eq!(game.lines_deleted(), 1);
+        assert!(reward > 0.0);
+        assert!((reward - f64::from(game.ep_return() - before)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn top_out_margin_counts_the_empty_rows_above_the_stack() {
+        assert_eq!(game_on(&[], 0).top_out_margin(), NUM_ROWS);
+        // Column 9 reaches row 2 from the top
+        let tower = vec![".........#"; NUM_ROWS - 2];
+        assert_eq!(game_on(&tower, 0).top_out_margin(), 2);
+    }
+
+    #[test]
+    fn generators_draw_bags_or_independent_pieces() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(8);
+        let mut bag = SevenBag::default();
+        assert!(bag.draws_bags());
+        for _ in 0..20 {
+            let mut drawn: Vec<usize> = (0..NUM_TETROMINOES).map(|_| bag.next(&mut rng)).collect();
+            drawn.sort_unstable();
+            assert_eq!(drawn, (0..NUM_TETROMINOES).collect::<Vec<_>>());
+        }
+
+        let mut uniform = UniformRandom;
+        assert!(!uniform.draws_bags());
+        let drawn: Vec<usize> = (0..700).map(|_| uniform.next(&mut rng)).collect();
+        let mut counts = [0; NUM_TETROMINOES];
+        for &piece in &drawn {
+            counts[piece] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 50), "{counts:?}");
+        let repeats = drawn.windows(2).filter(|pair| pair[0] == pair[1]).count();
+        assert!(repeats > 0);
+    }
 }
@@ -1,14 +1,15 @@
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
-use super::{Action, Game, GameResult, Player};
-
-type Cell = Option<Player>;
+use super::{Action, Cell, Game, GameResult, Player, grid_key, grid_material, grid_planes};
+use crate::render::Board;
 
 #[derive(Debug, Clone)]
 pub struct TicTacToe {
     board: [Cell; 9],
     current_player: Player,
     result: Option<GameResult>,
+    last_action: Option<Action>,
 }
 
 impl TicTacToe {
@@ -16,6 +17,19 @@ impl TicTacToe {
         self.result.is_some()
     }
 
+    /// The same position with the X and O marks swapped, so the player to move changes
+    /// sides. Flipping twice gives back the original position. With X a mark ahead, the
+    /// flipped position has O ahead and fails `validate`.
+    #[allow(dead_code)]
+    pub fn flip_perspective(&self) -> Self {
+        TicTacToe {
+            board: self.board.map(|cell| cell.map(Player::opponent)),
+            current_player: self.current_player.opponent(),
+            result: self.result.map(GameResult::swap_players),
+            last_action: self.last_action,
+        }
+    }
+
     fn update_result(&mut self) {
         const WIN_LINES: [[usize; 3]; 8] = [
             [0, 1, 2], // top row
@@ -44,16 +58,48 @@ impl TicTacToe {
     }
 }
 
+/// Maps a cell (row, col) to its position after a board rotation or reflection
+type Transform = fn(usize, usize) -> (usize, usize);
+
+const TRANSFORMS: [Transform; 8] = [
+    |r, c| (r, c),
+    |r, c| (c, 2 - r),
+    |r, c| (2 - r, 2 - c),
+    |r, c| (2 - c, r),
+    |r, c| (r, 2 - c),
+    |r, c| (2 - r, c),
+    |r, c| (c, r),
+    |r, c| (2 - c, 2 - r),
+];
+
 impl Default for TicTacToe {
     fn default() -> Self {
         TicTacToe {
             board: [None; 9],
             current_player: Player::X,
             result: None,
+            last_action: None,
         }
     }
 }
 
+// Positions with the same marks and player to move are equal however the marks were placed,
+// so the result and last action are left out
+impl PartialEq for TicTacToe {
+    fn eq(&self, other: &Self) -> bool {
+        self.board == other.board && self.current_player == other.current_player
+    }
+}
+
+impl Eq for TicTacToe {}
+
+impl Hash for TicTacToe {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.board.hash(state);
+        self.current_player.hash(state);
+    }
+}
+
 impl fmt::Display for TicTacToe {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for row in 0..3 {
@@ -76,6 +122,16 @@ impl fmt::Display for TicTacToe {
     }
 }
 
+impl Board for TicTacToe {
+    fn dimensions(&self) -> (usize, usize) {
+        (3, 3)
+    }
+
+    fn owner(&self, row: usize, col: usize) -> Option<Player> {
+        self.board[row * 3 + col]
+    }
+}
+
 impl Game for TicTacToe {
     fn print_instructions(&self) {
         println!("Tic-Tac-Toe with MCTS Agent");
@@ -106,6 +162,22 @@ impl Game for TicTacToe {
             .collect()
     }
 
+    /// Accepts a position, or a `row,col` coordinate
+    fn action_from_name(&self, name: &str) -> Result<Action, &'static str> {
+        let name = name.trim();
+        match name.split_once(',') {
+            Some((row, col)) => {
+                let row: usize = row.trim().parse().map_err(|_| "Invalid row")?;
+                let col: usize = col.trim().parse().map_err(|_| "Invalid column")?;
+                if row >= 3 || col >= 3 {
+                    return Err("Position out of bounds");
+                }
+                Ok(row * 3 + col)
+            }
+            None => name.parse().map_err(|_| "Unknown action"),
+        }
+    }
+
     fn current_player(&self) -> Player {
         self.current_player
     }
@@ -122,12 +194,179 @@ impl Game for TicTacToe {
         }
 
         self.board[action] = Some(self.current_player);
+        self.last_action = Some(action);
         self.update_result();
-        self.current_player = self.current_player.opponent();
+        self.current_player = self.next_player();
         Ok(())
     }
 
-    fn current_reward(&self) -> f64 {
-        0.0
+    /// Shape `[3, 3, 3]`: planes for empty, X and O cells
+    fn to_tensor(&self) -> (Vec<f32>, Vec<usize>) {
+        (grid_planes(&self.board), vec![3, 3, 3])
+    }
+
+    fn action_space_size(&self) -> usize {
+        9
+    }
+
+    fn legal_action_mask(&self) -> Vec<bool> {
+        let playing = !self.is_terminal();
+        self.board
+            .iter()
+            .map(|cell| playing && cell.is_none())
+            .collect()
+    }
+
+    fn symmetries(&self) -> Vec<Vec<Action>> {
+        TRANSFORMS
+            .iter()
+            .map(|transform| {
+                (0..9)
+                    .map(|i| {
+                        let (r, c) = transform(i / 3, i % 3);
+                        r * 3 + c
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|perm| (0..9).all(|i| self.board[perm[i]] == self.board[i]))
+            .collect()
+    }
+
+    fn ply(&self) -> usize {
+        self.board.iter().filter(|cell| cell.is_some()).count()
+    }
+
+    fn last_action(&self) -> Option<Action> {
+        self.last_action
+    }
+
+    fn material(&self) -> Vec<usize> {
+        grid_material(&self.board)
+    }
+
+    fn validate(&self) -> Result<(), &'static str> {
+        let material = self.material();
+        // X moves first, so X has as many pieces as O or one more
+        let (x, o) = (material[0], material[1]);
+        if x != o && x != o + 1 {
+            return Err("Players have unbalanced piece counts");
+        }
+        Ok(())
+    }
+
+    /// Cells row by row from the top, as X, O or .
+    fn state_key(&self) -> String {
+        grid_key(&self.board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(moves: &[Action]) -> TicTacToe {
+        let mut game = TicTacToe::default();
+        for &action in moves {
+            game.step(action).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn tensor_has_one_plane_per_cell_state() {
+        let (planes, shape) = play(&[4, 0]).to_tensor();
+        assert_eq!(shape, [3, 3, 3]);
+        assert_eq!(planes.len(), shape.iter().product::<usize>());
+        for cell in 0..9 {
+            let expected = match cell {
+                4 => [0.0, 1.0, 0.0],
+                0 => [0.0, 0.0, 1.0],
+                _ => [1.0, 0.0, 0.0],
+            };
+            let got: Vec<f32> = (0..3).map(|plane| planes[plane * 9 + cell]).collect();
+            assert_eq!(got, expected, "cell {cell}");
+        }
+    }
+
+    #[test]
+    fn symmetries_keep_the_marks_in_place() {
+        assert_eq!(TicTacToe::default().symmetries().len(), 8);
+        assert_eq!(play(&[4]).symmetries().len(), 8);
+        let corner = play(&[0]).symmetries();
+        assert_eq!(corner.len(), 2);
+        assert!(corner.iter().all(|perm| perm[0] == 0 && perm[8] == 8));
+        assert_eq!(play(&[0, 5]).symmetries().len(), 1);
+    }
+
+    #[test]
+    fn winning_actions_complete_a_line() {
+        let game = play(&[0, 3, 1, 4]);
+        assert!(game.is_winning_action(2));
+        assert!(!game.is_winning_action(5));
+        assert!(!game.is_winning_action(0));
+        let blocked = play(&[0, 3, 1, 4, 8]);
+        assert!(blocked.is_winning_action(5));
+        assert!(!blocked.is_winning_action(2));
+    }
+
+    #[test]
+    fn last_action_tracks_accepted_moves() {
+        let mut game = TicTacToe::default();
+        assert_eq!(game.last_action(), None);
+        game.step(4).unwrap();
+        assert!(game.step(4).is_err());
+        assert_eq!(game.last_action(), Some(4));
+        game.step(0).unwrap();
+        assert_eq!(game.last_action(), Some(0));
+    }
+
+    #[test]
+    fn actions_parse_from_positions_and_coordinates() {
+        let game = TicTacToe::default();
+        for action in game.allowed_actions() {
+            assert_eq!(game.action_from_name(&game.action_name(action)), Ok(action));
+        }
+        assert_eq!(game.action_from_name("1, 2"), Ok(5));
+        assert_eq!(game.action_from_name(" 7 "), Ok(7));
+        assert_eq!(game.action_from_name("3,0"), Err("Position out of bounds"));
+        assert_eq!(game.action_from_name("a,1"), Err("Invalid row"));
+        assert_eq!(game.action_from_name("center"), Err("Unknown action"));
+    }
+
+    #[test]
+    fn flipping_swaps_the_sides() {
+        let game = play(&[4, 0, 8]);
+        let flipped = game.flip_perspective();
+        assert_eq!(flipped.current_player(), Player::X);
+        assert_eq!(flipped.state_key(), "X...O...O");
+        let back = flipped.flip_perspective();
+        assert_eq!(back, game);
+        assert_eq!(back.result(), game.result());
+
+        let won = play(&[0, 3, 1, 4, 2]);
+        assert_eq!(
+            won.flip_perspective().result(),
+            Some(GameResult::Win(Player::O))
+        );
+    }
+
+    #[test]
+    fn validation_requires_x_to_move_first() {
+        assert_eq!(play(&[]).validate(), Ok(()));
+        assert_eq!(play(&[4]).validate(), Ok(()));
+        assert_eq!(play(&[4, 0]).validate(), Ok(()));
+        let mut ahead = TicTacToe::default();
+        ahead.board[0] = Some(Player::O);
+        assert!(ahead.validate().is_err());
+        let mut two_ahead = play(&[4]);
+        two_ahead.board[8] = Some(Player::X);
+        assert!(two_ahead.validate().is_err());
+        assert!(play(&[4]).flip_perspective().validate().is_err());
+    }
+
+    #[test]
+    fn material_counts_each_players_marks() {
+        assert_eq!(TicTacToe::default().material(), [0, 0]);
+        assert_eq!(play(&[0, 1, 2, 3, 4]).material(), [3, 2]);
     }
 }
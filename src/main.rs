@@ -1,20 +1,158 @@
 #![warn(clippy::all, clippy::pedantic)]
 
+mod arena;
+mod bench;
 mod game;
 mod mcts;
+mod regress;
+mod render;
+mod serve;
+mod solver;
 
+use arena::FirstPlayer;
 use argh::FromArgs;
-use game::{Game, GameResult, Player, connect4::Connect4, tictactoe::TicTacToe};
-use mcts::Mcts;
+use game::{Action, Game, GameResult, Player, connect4::Connect4, tictactoe::TicTacToe};
+use mcts::{
+    BackupRule, Decay, ExpansionOrder, ExplorationSchedule, FinalSelection, Mcts, MctsConfig,
+    RootNoise, WorkerSeeding,
+};
+use render::{Board, BoardClient};
 use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 
-use crate::game::tetris::Tetris;
+use crate::game::tetris::{AutoShift, StackShaping, Tetris, UniformRandom};
+
+/// Options of the top-level command that apply to any game launched by name
+#[derive(Clone, Copy)]
+struct PlayOptions {
+    ponder: bool,
+    blunder_threshold: Option<f64>,
+}
+
+/// A built-in game, to list it and launch it by name with `play` or `serve`
+struct GameEntry {
+    name: &'static str,
+    /// The game in its default setup
+    create: fn() -> Box<dyn game::GameDyn>,
+    /// Play the default setup, given the agent config for an iteration budget
+    play: fn(PlayOptions, &dyn Fn(u32) -> MctsConfig),
+}
+
+const GAMES: [GameEntry; 3] = [
+    GameEntry {
+        name: "tictactoe",
+        create: || Box::new(TicTacToe::default()),
+        play: |options, config| {
+            play_game(
+                TicTacToe::default(),
+                false,
+                options.ponder,
+                false,
+                options.blunder_threshold,
+                config(10_000),
+            );
+        },
+    },
+    GameEntry {
+        name: "connect4",
+        create: || Box::new(Connect4::default()),
+        play: |options, config| {
+            play_game(
+                Connect4::default(),
+                false,
+                options.ponder,
+                false,
+                options.blunder_threshold,
+                config(10_000),
+            );
+        },
+    },
+    GameEntry {
+        name: "tetris",
+        create: || Box::new(Tetris::new()),
+        play: |_, config| play_tetris(&mut Tetris::new(), false, config(32_000)),
+    },
+];
+
+/// The built-in game called `name`
+fn find_game(name: &str) -> Option<&'static GameEntry> {
+    GAMES.iter().find(|entry| entry.name == name)
+}
 
 #[derive(FromArgs)]
+#[allow(clippy::struct_excessive_bools)]
 /// Play games against an MCTS agent
 struct Args {
     #[argh(subcommand)]
     game: GameCommand,
+    /// extra iterations the agent may spend until its most visited and best valued moves agree
+    #[argh(option)]
+    robust_max: Option<u32>,
+    /// how the agent picks its move: most-visits, max-value or auto (max-value when playing
+    /// alone), unless --robust-max is given
+    #[argh(option, default = "FinalSelection::Auto")]
+    final_selection: FinalSelection,
+    /// only keep the game state of every Nth tree depth, rebuilding the others on demand
+    #[argh(option, default = "1")]
+    state_interval: usize,
+    /// probability of a random rollout move where the game knows a good move (1.0: always random)
+    #[argh(option, default = "1.0")]
+    rollout_epsilon: f64,
+    /// visits each move gets before the agent trusts its statistics
+    #[argh(option, default = "0")]
+    min_visits: u32,
+    /// search openings instead of playing book moves
+    #[argh(switch)]
+    no_book: bool,
+    /// let the agent keep searching while you think
+    #[argh(switch)]
+    ponder: bool,
+    /// number of search trees the agent grows in parallel
+    #[argh(option, default = "1")]
+    threads: usize,
+    /// value rewards by how few moves they take to reach
+    #[argh(switch)]
+    reward_per_move: bool,
+    /// print how the agent's best move and its value evolved during each search
+    #[argh(switch)]
+    checkpoints: bool,
+    /// order in which the agent first tries moves: last, first or random
+    #[argh(option, default = "ExpansionOrder::Last")]
+    expansion_order: ExpansionOrder,
+    /// how rollout rewards combine into move values: average or max (single-player games only)
+    #[argh(option, default = "BackupRule::Average")]
+    backup_rule: BackupRule,
+    /// mix Dirichlet noise with this concentration into the agent's first choice of move
+    #[argh(option)]
+    root_noise_alpha: Option<f64>,
+    /// weight of the root noise bonus added to the move scores
+    #[argh(option, default = "0.25")]
+    root_noise_epsilon: f64,
+    /// decay the agent's exploration from this positive scale of the usual amount over each search
+    #[argh(option, from_str_fn(positive_scale))]
+    exploration_start: Option<f64>,
+    /// positive exploration scale the decay reaches at the end of each search
+    #[argh(option, default = "0.25", from_str_fn(positive_scale))]
+    exploration_end: f64,
+    /// how exploration decays: linear or exponential
+    #[argh(option, default = "Decay::Linear")]
+    exploration_decay: Decay,
+    /// deepest tree level the agent searches before rolling out, at least 1
+    #[argh(option, from_str_fn(select_depth))]
+    max_select_depth: Option<usize>,
+    /// point out your moves scoring this much less than the best move (0 to 1)
+    #[argh(option)]
+    blunder_threshold: Option<f64>,
+    /// most tree nodes the agent may grow per search, at least 2
+    #[argh(option, from_str_fn(node_budget))]
+    node_budget: Option<usize>,
+    /// seed parallel search trees from a process-wide counter instead of the agent seed
+    #[argh(switch)]
+    shared_seeds: bool,
+    /// play immediate wins and block immediate losses without relying on the search
+    #[argh(switch)]
+    tactical_scan: bool,
 }
 
 #[derive(FromArgs)]
@@ -23,64 +161,365 @@ enum GameCommand {
     TicTacToe(TicTacToeCmd),
     Connect4(Connect4Cmd),
     Tetris(TetrisCmd),
+    Bench(BenchCmd),
+    Regress(RegressCmd),
+    Arena(ArenaCmd),
+    Play(PlayCmd),
+    Serve(ServeCmd),
 }
 
 #[derive(FromArgs)]
 #[argh(subcommand, name = "tictactoe")]
 /// Play Tic-Tac-Toe
-struct TicTacToeCmd {}
+struct TicTacToeCmd {
+    /// show the board in a window
+    #[argh(switch)]
+    render: bool,
+    /// play against an unbeatable exact solver instead of MCTS
+    #[argh(switch)]
+    perfect: bool,
+}
 
 #[derive(FromArgs)]
 #[argh(subcommand, name = "connect4")]
 /// Play Connect 4
-struct Connect4Cmd {}
+struct Connect4Cmd {
+    /// number of board rows
+    #[argh(option, default = "6")]
+    rows: usize,
+    /// number of board columns
+    #[argh(option, default = "7")]
+    cols: usize,
+    /// number of pieces in a line needed to win
+    #[argh(option, default = "4")]
+    win_len: usize,
+    /// show the board in a window
+    #[argh(switch)]
+    render: bool,
+}
 
 #[derive(FromArgs)]
 #[argh(subcommand, name = "tetris")]
-/// Play Connect 4
-struct TetrisCmd {}
+#[allow(clippy::struct_excessive_bools)]
+/// Play Tetris
+struct TetrisCmd {
+    /// print a column height/hole heatmap after each move
+    #[argh(switch)]
+    heatmap: bool,
+    /// start from a board file made of `.` (empty) and `#` (filled) rows
+    #[argh(option)]
+    grid: Option<String>,
+    /// falling tetromino id (0-6) when starting from a board file
+    #[argh(option, default = "0")]
+    piece: usize,
+    /// don't let the agent consider doing nothing
+    #[argh(switch)]
+    no_noop: bool,
+    /// file keeping the personal best score, updated on a new record
+    #[argh(option)]
+    best_file: Option<String>,
+    /// end the game after this many ticks
+    #[argh(option)]
+    tick_limit: Option<usize>,
+    /// keep tetrominoes from falling on their own
+    #[argh(switch)]
+    no_gravity: bool,
+    /// let the agent drop tetrominoes to the bottom without locking them
+    #[argh(switch)]
+    sonic_drop: bool,
+    /// draw each tetromino uniformly at random instead of from 7-piece bags (empty grid only)
+    #[argh(switch)]
+    uniform_pieces: bool,
+    /// play yourself with the keyboard instead of watching the agent
+    #[argh(switch)]
+    human: bool,
+    /// frames a direction key is held before it repeats, when playing yourself
+    #[argh(option, default = "10")]
+    das: u32,
+    /// frames between repeated moves of a held direction key, when playing yourself
+    #[argh(option, default = "2")]
+    arr: u32,
+    /// points for clearing 0, 1, 2... lines at once, the last applying to larger clears
+    /// (repeat for each count)
+    #[argh(option)]
+    line_score: Vec<usize>,
+    /// reward for clearing 0, 1, 2... lines at once, the last applying to larger clears
+    /// (repeat for each count)
+    #[argh(option)]
+    line_reward: Vec<f32>,
+    /// reward cost of each hole a lock adds to the stack, shaping the agent's play
+    #[argh(option)]
+    hole_weight: Option<f32>,
+    /// reward cost of each row of height a lock adds to the stack, shaping the agent's play
+    #[argh(option)]
+    height_weight: Option<f32>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "bench")]
+/// Measure MCTS search throughput
+struct BenchCmd {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "regress")]
+/// Check that seeded searches still choose the golden moves
+struct RegressCmd {}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "arena")]
+/// Rate agents with different iteration budgets against each other at Connect 4
+struct ArenaCmd {
+    /// iterations per search of one agent (repeat for each agent)
+    #[argh(option)]
+    iters: Vec<u32>,
+    /// games per pair of agents
+    #[argh(option, default = "4")]
+    games: usize,
+    /// pick who moves first at random from this seed, instead of taking turns
+    #[argh(option)]
+    random_first: Option<u64>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "play")]
+/// Play a game by name with default settings
+struct PlayCmd {
+    /// name of the game
+    #[argh(positional)]
+    name: Option<String>,
+    /// list the available games
+    #[argh(switch)]
+    list: bool,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand, name = "serve")]
+/// Play a game for another program, exchanging JSON lines over stdin and stdout
+struct ServeCmd {
+    /// name of the game: tictactoe, connect4 or tetris
+    #[argh(positional)]
+    name: String,
+}
+
+/// Parse a count of at least `min`
+fn count_at_least(value: &str, min: usize) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(count) if count >= min => Ok(count),
+        _ => Err(format!(
+            "expected a whole number of at least {min}, got {value}"
+        )),
+    }
+}
+
+/// Parse a select depth, which must let the search expand the root
+fn select_depth(value: &str) -> Result<usize, String> {
+    count_at_least(value, 1)
+}
+
+/// Parse a node budget, which must leave room for the root's first child
+fn node_budget(value: &str) -> Result<usize, String> {
+    count_at_least(value, 2)
+}
+
+/// Parse a scale that must be positive, like the exploration scales
+fn positive_scale(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(scale) if scale > 0.0 => Ok(scale),
+        _ => Err(format!("expected a positive number, got {value}")),
+    }
+}
 
 fn main() {
     let args: Args = argh::from_env();
+    let final_selection = args
+        .robust_max
+        .map_or(args.final_selection, |max_extra_iters| {
+            FinalSelection::RobustMax { max_extra_iters }
+        });
+    let config = |iters| MctsConfig {
+        iters,
+        final_selection,
+        state_interval: args.state_interval,
+        epsilon_greedy_rollout: args.rollout_epsilon,
+        min_visits: args.min_visits,
+        opening_book: !args.no_book,
+        threads: args.threads,
+        reward_per_move: args.reward_per_move,
+        record_checkpoints: args.checkpoints,
+        expansion_order: args.expansion_order,
+        backup_rule: args.backup_rule,
+        root_noise: args.root_noise_alpha.map(|alpha| RootNoise {
+            alpha,
+            epsilon: args.root_noise_epsilon,
+        }),
+        max_select_depth: args.max_select_depth,
+        node_budget: args.node_budget,
+        worker_seeding: if args.shared_seeds {
+            WorkerSeeding::Shared
+        } else {
+            WorkerSeeding::PerWorker
+        },
+        tactical_scan: args.tactical_scan,
+        exploration_schedule: args.exploration_start.map(|start| ExplorationSchedule {
+            start,
+            end: args.exploration_end,
+            decay: args.exploration_decay,
+        }),
+        ..MctsConfig::default()
+    };
 
+    let options = PlayOptions {
+        ponder: args.ponder,
+        blunder_threshold: args.blunder_threshold,
+    };
     match args.game {
-        GameCommand::TicTacToe(_) => play_game(TicTacToe::default()),
-        GameCommand::Connect4(_) => play_game(Connect4::default()),
-        GameCommand::Tetris(_) => play_tetris(Tetris::new()),
+        GameCommand::TicTacToe(cmd) => {
+            let game = TicTacToe::default();
+            play_game(
+                game,
+                cmd.render,
+                args.ponder,
+                cmd.perfect,
+                args.blunder_threshold,
+                config(10_000),
+            );
+        }
+        GameCommand::Connect4(cmd) => {
+            let game = Connect4::new(cmd.rows, cmd.cols, cmd.win_len);
+            play_game(
+                game,
+                cmd.render,
+                args.ponder,
+                false,
+                args.blunder_threshold,
+                config(10_000),
+            );
+        }
+        GameCommand::Tetris(cmd) => run_tetris(cmd, &config),
+        GameCommand::Bench(_) => {
+            println!("{:.0} iterations/s", bench::bench_search());
+        }
+        GameCommand::Regress(_) => {
+            let mismatches = regress::check_golden();
+            if mismatches.is_empty() {
+                println!("All golden moves and trees match");
+            } else {
+                for mismatch in &mismatches {
+                    println!("{mismatch}");
+                }
+                std::process::exit(1);
+            }
+        }
+        GameCommand::Arena(cmd) => run_arena(&cmd, &config),
+        GameCommand::Play(cmd) => {
+            let entry = cmd.name.as_deref().and_then(find_game);
+            match entry {
+                Some(entry) if !cmd.list => (entry.play)(options, &config),
+                _ => {
+                    if let (Some(name), None) = (&cmd.name, entry) {
+                        println!("Unknown game: {name}");
+                    }
+                    println!("Available games:");
+                    for entry in &GAMES {
+                        println!("  {}", entry.name);
+                    }
+                }
+            }
+        }
+        GameCommand::Serve(cmd) => serve_game(&cmd.name),
     }
 }
 
-fn play_game<G: Game + std::fmt::Display>(mut game: G) {
+fn play_game<G: Game + Board + std::fmt::Display + Send>(
+    mut game: G,
+    render: bool,
+    ponder: bool,
+    perfect: bool,
+    blunder_threshold: Option<f64>,
+    config: MctsConfig,
+) {
     game.print_instructions();
 
-    let mut agent = Mcts::new(10_000);
+    let iters = config.iters;
+    let coach_config = MctsConfig {
+        opening_book: false,
+        ..config.clone()
+    };
+    let mut agent = Mcts::with_config(config);
+    // Whether the agent tree is rooted at the current position, to ponder on and reuse
+    let mut tree_reusable = false;
+    let mut client = render.then(|| BoardClient::new(&game, "MCTS"));
+    // States before each of the human's moves, to take back a move and the agent reply
+    let mut history = vec![];
 
     loop {
         println!("{game}\n");
+        if let Some(action) = game.last_action() {
+            println!("Last move: {}", game.action_name(action));
+        }
+        if let Some(client) = &mut client {
+            client.render(&game);
+        }
 
         match game.current_player() {
             Player::X => {
                 let actions = game.allowed_actions();
                 let max_action = actions.iter().max().unwrap_or(&0);
-                print!("Your move (0-{max_action}): ");
+                print!("Your move (0-{max_action}, u to undo, a to analyze): ");
                 io::stdout().flush().unwrap();
 
-                let mut input = String::new();
-                io::stdin().read_line(&mut input).unwrap();
+                let input = if tree_reusable {
+                    read_line_pondering(&mut agent)
+                } else {
+                    read_line()
+                };
 
-                if let Ok(pos) = input.trim().parse::<usize>() {
-                    if let Err(e) = game.step(pos) {
-                        println!("Invalid move: {e}");
+                if input.trim() == "a" {
+                    print_analysis(&game, &coach_config);
+                } else if input.trim() == "u" {
+                    if undo(&mut game, &mut history) {
+                        tree_reusable = false;
+                    } else {
+                        println!("Nothing to undo");
                     }
                 } else {
-                    println!("Please enter a valid number");
+                    let previous = game.clone();
+                    match game
+                        .action_from_name(&input)
+                        .and_then(|pos| game.step(pos).map(|()| pos))
+                    {
+                        Ok(pos) => {
+                            if let Some(threshold) = blunder_threshold {
+                                report_blunder(&previous, pos, threshold, &coach_config);
+                            }
+                            history.push(previous);
+                            tree_reusable = tree_reusable && agent.advance_root(pos);
+                        }
+                        Err(e) => println!("Invalid move: {e}"),
+                    }
+                }
+            }
+            Player::O if perfect => {
+                if let Some(action) = solver::perfect_move(&game) {
+                    println!("Perfect play: {}", game.action_name(action));
+                    game.step(action).unwrap();
                 }
             }
             Player::O => {
                 println!("MCTS is thinking...");
-                if let Some(action) = agent.search(&game) {
-                    println!("MCTS plays: {action}");
+                let action = if tree_reusable {
+                    agent.continue_search(iters)
+                } else {
+                    agent.search_parallel(&game)
+                };
+                if let Some(action) = action {
+                    report_search(&agent, &game, action);
+                    let reply = agent.expected_reply();
                     game.step(action).unwrap();
+                    if let Some(reply) = reply {
+                        println!("MCTS expects you to play {}", game.action_name(reply));
+                    }
+                    tree_reusable = ponder && agent.advance_root(action);
                 }
             }
         }
@@ -89,35 +528,252 @@ fn play_game<G: Game + std::fmt::Display>(mut game: G) {
             match result {
                 GameResult::Win(Player::X) => println!("You win!"),
                 GameResult::Win(Player::O) => println!("MCTS wins!"),
-                GameResult::Draw => println!("It's a draw!"),
-                GameResult::End(_) => eprintln!("GAME RESULT ERROR"),
+                _ => println!("{result}"),
             }
             println!("\nFinal board:\n{game}\n");
+            if let Some(client) = &mut client {
+                client.render(&game);
+            }
             break;
         }
     }
 }
 
-fn play_tetris(mut game: Tetris) {
+/// Go back to the state before the human's last move, taking back the agent reply too.
+/// Returns false if there is no move to take back.
+fn undo<G: Game>(game: &mut G, history: &mut Vec<G>) -> bool {
+    match history.pop() {
+        Some(previous) => {
+            *game = previous;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Play Tetris as set up by `cmd`, yourself or by watching the agent
+fn run_tetris(cmd: TetrisCmd, config: &dyn Fn(u32) -> MctsConfig) {
+    let mut game = match cmd.grid {
+        Some(path) => {
+            let grid = std::fs::read_to_string(path).expect("Failed to read grid file");
+            Tetris::from_grid_str(&grid, cmd.piece, None).expect("Invalid grid file")
+        }
+        None if cmd.uniform_pieces => Tetris::with_generator(UniformRandom),
+        None => Tetris::new(),
+    };
+    game.set_noop_allowed(!cmd.no_noop);
+    game.set_tick_limit(cmd.tick_limit);
+    game.set_gravity(!cmd.no_gravity);
+    game.set_sonic_drop_allowed(cmd.sonic_drop);
+    game.set_combo_curves(cmd.line_score, cmd.line_reward);
+    if cmd.hole_weight.is_some() || cmd.height_weight.is_some() {
+        game.set_stack_shaping(Some(StackShaping {
+            hole_weight: cmd.hole_weight.unwrap_or(0.0),
+            height_weight: cmd.height_weight.unwrap_or(0.0),
+        }));
+    }
+    if let Some(best) = cmd
+        .best_file
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|best| best.trim().parse().ok())
+    {
+        game.set_personal_best(best);
+    }
+    if cmd.human {
+        let mut client = game.render_client();
+        game.play(&mut client, AutoShift::new(cmd.das, cmd.arr));
+        println!("Score: {}", game.score());
+    } else {
+        play_tetris(&mut game, cmd.heatmap, config(32_000));
+    }
+    if game.is_new_record()
+        && let Some(path) = cmd.best_file
+    {
+        std::fs::write(path, game.score().to_string()).expect("Failed to save best score");
+    }
+}
+
+/// Rate agents with the iteration budgets of `cmd` against each other at Connect 4
+fn run_arena(cmd: &ArenaCmd, config: &dyn Fn(u32) -> MctsConfig) {
+    let configs: Vec<MctsConfig> = cmd.iters.iter().map(|&iters| config(iters)).collect();
+    let first_player = cmd
+        .random_first
+        .map_or(FirstPlayer::Alternate, FirstPlayer::Random);
+    let outcomes = arena::round_robin(&Connect4::default(), &configs, cmd.games, first_player);
+    let ratings = arena::compute_elo(&outcomes);
+    let sides = arena::side_stats(&outcomes);
+    for (id, iters) in cmd.iters.iter().enumerate() {
+        let rating = ratings.get(&id).copied().unwrap_or(arena::INITIAL_RATING);
+        let side = sides.get(&id).copied().unwrap_or_default();
+        println!(
+            "{iters} iterations: {rating:.0} (wins {:.0}% first, {:.0}% second)",
+            100.0 * side.first_win_rate(),
+            100.0 * side.second_win_rate()
+        );
+    }
+}
+
+/// Serve the game called `name` over stdin and stdout
+fn serve_game(name: &str) {
+    let Some(entry) = find_game(name) else {
+        eprintln!("Unknown game: {name}");
+        return;
+    };
+    serve::serve((entry.create)(), io::stdin().lock(), io::stdout().lock())
+        .expect("Failed to serve the game");
+}
+
+/// Print the action chosen by the agent and statistics of its search
+fn report_search<G: Game>(agent: &Mcts<G>, game: &G, action: Action) {
+    println!(
+        "MCTS plays: {} ({} visits, {} nodes, depth {})",
+        game.action_name(action),
+        agent.action_visits(action),
+        agent.tree_size(),
+        agent.max_depth()
+    );
+    for &(iteration, best, value) in agent.checkpoints() {
+        println!(
+            "  after {iteration} iterations: {} ({value:.3})",
+            game.action_name(best)
+        );
+    }
+    let unexpanded = agent.unexpanded_root_actions();
+    if !unexpanded.is_empty() {
+        println!("MCTS never tried: {unexpanded:?}");
+    }
+}
+
+/// Print the value of each move of `game` for the player to move, best first
+fn print_analysis<G: Game>(game: &G, config: &MctsConfig) {
+    let mut analyst = Mcts::with_config(config.clone());
+    let analysis = analyst.analyze(game);
+    let regrets = analyst.action_regrets();
+    let policy = analyst.policy_target(game.action_space_size());
+    for (action, value, visits) in analysis {
+        let regret = regrets
+            .iter()
+            .find(|&&(regretted, _)| regretted == action)
+            .map_or(0.0, |&(_, regret)| regret);
+        println!(
+            "  {}: {value:.3} ({visits} visits, {:.0}% of all, regret {regret:.3})",
+            game.action_name(action),
+            100.0 * policy[action]
+        );
+    }
+}
+
+/// Tell the human if `played` was much worse than the best move of `game`
+fn report_blunder<G: Game>(game: &G, played: Action, threshold: f64, config: &MctsConfig) {
+    let mut coach = Mcts::with_config(config.clone());
+    if let Some(gap) = coach.value_gap(game, played)
+        && gap > threshold
+    {
+        println!(
+            "Blunder: {} scores {gap:.2} less than the best move",
+            game.action_name(played)
+        );
+        if let Ok((Some(reply), _)) = coach.search_after(game, played) {
+            println!("  It is refuted by {}", game.action_name(reply));
+        }
+    }
+}
+
+fn read_line() -> String {
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input
+}
+
+/// Read a line while the agent keeps searching in the background
+fn read_line_pondering<G: Game + Send>(agent: &mut Mcts<G>) -> String {
+    let stop = AtomicBool::new(false);
+    thread::scope(|s| {
+        s.spawn(|| agent.ponder(&stop));
+        let input = read_line();
+        stop.store(true, Ordering::Relaxed);
+        input
+    })
+}
+
+fn play_tetris(game: &mut Tetris, heatmap: bool, config: MctsConfig) {
     game.print_instructions();
 
-    let mut agent = Mcts::new(32_000);
+    let mut agent = Mcts::with_config(config);
     let mut client = game.render_client();
+    let mut new_record = false;
 
     loop {
-        if let Some(action) = agent.search(&game) {
-            println!(
-                "Agent selected: {:?}",
-                game::tetris::Action::from(action as u8)
-            );
-            Game::step(&mut game, action).unwrap();
+        if let Some(action) = agent.search(game) {
+            println!("Agent selected: {}", game.action_name(action));
+            Game::step(game, action).unwrap();
+            if game.last_step_reward() != 0.0 {
+                println!("Reward: {:.3}", game.last_step_reward());
+            }
+            if game.is_new_record() && !new_record {
+                println!("NEW RECORD!");
+                new_record = true;
+            }
             game.render(&mut client);
+            if heatmap {
+                println!("{}", game.debug_heatmap());
+            }
         } else {
-            println!("No action possible")
+            println!("No action possible");
         }
-        if let Some(GameResult::End(result)) = game.result() {
-            println!("Final score: {result}");
+        if game.result().is_some() {
+            println!("Game over, score {}", game.score());
+            println!(
+                "Lines cleared: {}, return: {:.3} ({:.3} per tick)",
+                game.lines_deleted(),
+                game.ep_return(),
+                game.normalized_return()
+            );
+            println!("Tetrominoes spawned by id: {:?}", game.deck_statistics());
             break;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{GAMES, TetrisCmd, find_game, undo};
+    use crate::game::{Game, tictactoe::TicTacToe};
+    use argh::FromArgs;
+
+    #[test]
+    fn registered_games_are_found_by_name_and_start_unfinished() {
+        for entry in &GAMES {
+            assert!(find_game(entry.name).is_some_and(|found| found.name == entry.name));
+            let game = (entry.create)();
+            assert!(game.result_with_limit().is_none());
+            assert!(!game.allowed_actions().is_empty());
+        }
+        assert!(find_game("chess").is_none());
+    }
+
+    #[test]
+    fn undo_takes_back_the_move_and_the_reply() {
+        let mut game = TicTacToe::default();
+        let mut history = vec![];
+        for (human, agent) in [(4, 0), (8, 2)] {
+            history.push(game.clone());
+            game.step(human).unwrap();
+            game.step(agent).unwrap();
+        }
+        assert!(undo(&mut game, &mut history));
+        assert_eq!(game.state_key(), "O...X....");
+        assert!(undo(&mut game, &mut history));
+        assert_eq!(game, TicTacToe::default());
+        assert!(!undo(&mut game, &mut history));
+        assert_eq!(game, TicTacToe::default());
+    }
+
+    #[test]
+    fn line_scores_must_not_be_negative() {
+        let parse = |score: &str| TetrisCmd::from_args(&["tetris"], &["--line-score", score]);
+        assert_eq!(parse("300").unwrap().line_score, [300]);
+        assert!(parse("-300").is_err());
+    }
+}
@@ -1,40 +1,661 @@
 use crate::game::{Action, Game, GameResult, Player};
+use once_cell::sync::Lazy;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+
+/// Spacing between worker seeds of a parallel search (2^64 / golden ratio)
+const SEED_SPACING: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Next worker seed handed out to parallel searches with shared seeding
+static SHARED_SEEDS: Lazy<AtomicU64> = Lazy::new(|| AtomicU64::new(fastrand::u64(..)));
 
 pub struct Mcts<G> {
     nodes: Vec<Node<G>>,
-    iters: u32,
+    config: MctsConfig,
+    rng: fastrand::Rng,
+    /// Iterations run since the last `search`, including continued searches and pondering
+    iterations: u32,
+    /// (iteration, best action, root value) recorded when `record_checkpoints` is set
+    checkpoints: Vec<(u32, Action, f64)>,
+    /// Dirichlet noise of each root action, when `root_noise` is set
+    noise: BTreeMap<Action, f64>,
+    /// Emptied children buffers of dropped nodes, to reuse instead of allocating
+    spare_children: Vec<Vec<usize>>,
+    /// Emptied unvisited action buffers of dropped nodes, to reuse instead of allocating
+    spare_actions: Vec<Vec<Action>>,
+    reused_buffers: usize,
+}
+
+#[derive(Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct MctsConfig {
+    /// Number of iterations per search
+    pub iters: u32,
+    /// Blend All-Moves-As-First statistics into selection (RAVE) using this bias.
+    /// Smaller values keep trusting AMAF statistics for longer.
+    pub rave_bias: Option<f64>,
+    /// Only expand one root action per class of symmetric actions
+    pub reduce_symmetries: bool,
+    /// Seed for rollouts, for reproducible searches.
+    /// Parallel searches derive the seed of each worker from it.
+    pub seed: Option<u64>,
+    /// Number of trees searched in parallel by `search_parallel`
+    pub threads: usize,
+    /// How the final action is chosen among the root children
+    pub final_selection: FinalSelection,
+    /// Only keep the state of nodes at depths that are a multiple of this interval.
+    /// Other states are rebuilt on demand by replaying actions from the closest kept ancestor,
+    /// trading CPU for memory.
+    pub state_interval: usize,
+    /// Probability of a random rollout move when the game offers a heuristic move.
+    /// 1.0 keeps rollouts fully random.
+    pub epsilon_greedy_rollout: f64,
+    /// Visits a child needs before selection trusts its statistics.
+    /// Less visited children are always selected first.
+    pub min_visits: u32,
+    /// Play the game's opening book moves without searching
+    pub opening_book: bool,
+    /// Divide `End` reward gains by the number of moves from the root to the end of the
+    /// rollout, so reaching a reward sooner is worth more
+    pub reward_per_move: bool,
+    /// Record the best action and root value after iterations 1, 2, 4, 8, ...
+    /// and at the end of each search, to study convergence
+    pub record_checkpoints: bool,
+    /// Which unvisited action of a node is expanded next
+    pub expansion_order: ExpansionOrder,
+    /// How rollout rewards are aggregated into node values
+    pub backup_rule: BackupRule,
+    /// Dirichlet noise mixed into the selection scores of root children, to diversify
+    /// self-play games
+    pub root_noise: Option<RootNoise>,
+    /// Depth below which selection never descends, rolling out from the nodes at that depth.
+    /// The root is always expanded, so a depth of 0 acts as 1.
+    pub max_select_depth: Option<usize>,
+    /// Stop searching once the tree has this many nodes, even with iterations left.
+    /// The root always gets a child, so budgets below 2 act as 2.
+    pub node_budget: Option<usize>,
+    /// Where the workers of a parallel search get their seeds
+    pub worker_seeding: WorkerSeeding,
+    /// Before searching, play an immediate win if there is one, and otherwise only search
+    /// the moves that stop an immediate win of the opponent
+    pub tactical_scan: bool,
+    /// Scale of the UCB exploration term decaying over the iteration budget,
+    /// to explore early and exploit late. The scale stays 1 without a schedule.
+    pub exploration_schedule: Option<ExplorationSchedule>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorkerSeeding {
+    /// Seeds derived from the config seed, so parallel searches are reproducible
+    PerWorker,
+    /// Seeds drawn from a counter shared by all searches of the process, so concurrent
+    /// searches never share a random stream
+    Shared,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RootNoise {
+    /// Concentration of the Dirichlet distribution. Smaller values favor fewer actions.
+    pub alpha: f64,
+    /// Weight of the noise bonus added to the UCB score
+    pub epsilon: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExplorationSchedule {
+    /// Exploration scale at the first iteration
+    pub start: f64,
+    /// Exploration scale once the iteration budget is spent
+    pub end: f64,
+    /// Exponential decay needs positive scales, and decays linearly otherwise
+    pub decay: Decay,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decay {
+    /// Interpolate linearly from the start to the end scale
+    Linear,
+    /// Multiply the scale by the same factor every iteration
+    Exponential,
+}
+
+impl FromStr for Decay {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Decay::Linear),
+            "exponential" => Ok(Decay::Exponential),
+            _ => Err("Expected linear or exponential"),
+        }
+    }
+}
+
+/// Outcome of a node proven by the search, for the node actor
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Proof {
+    Win,
+    Loss,
+}
+
+impl Proof {
+    fn for_opponent(self) -> Self {
+        match self {
+            Proof::Win => Proof::Loss,
+            Proof::Loss => Proof::Win,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FinalSelection {
+    /// The most visited child
+    MostVisits,
+    /// The most visited child, after up to `max_extra_iters` more iterations on the most
+    /// visited and highest valued children until they are the same
+    RobustMax { max_extra_iters: u32 },
+    /// The child with the highest mean reward
+    MaxValue,
+    /// `MaxValue` for single-player games, where the searching player makes every decision,
+    /// and `MostVisits` otherwise
+    Auto,
+}
+
+impl FromStr for FinalSelection {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "most-visits" => Ok(FinalSelection::MostVisits),
+            "max-value" => Ok(FinalSelection::MaxValue),
+            "auto" => Ok(FinalSelection::Auto),
+            _ => Err("Expected most-visits, max-value or auto"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpansionOrder {
+    /// The last allowed action first
+    Last,
+    /// Allowed actions in the order the game lists them
+    First,
+    /// A random unvisited action, drawn from the search RNG
+    Random,
+}
+
+impl FromStr for ExpansionOrder {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last" => Ok(ExpansionOrder::Last),
+            "first" => Ok(ExpansionOrder::First),
+            "random" => Ok(ExpansionOrder::Random),
+            _ => Err("Expected last, first or random"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackupRule {
+    /// The mean reward of the rollouts through a node
+    Average,
+    /// The best reward of the rollouts through a node. Only suits single-player games,
+    /// where the searching player controls every decision.
+    Max,
+}
+
+impl FromStr for BackupRule {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "average" => Ok(BackupRule::Average),
+            "max" => Ok(BackupRule::Max),
+            _ => Err("Expected average or max"),
+        }
+    }
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            iters: 10_000,
+            rave_bias: None,
+            reduce_symmetries: false,
+            seed: None,
+            threads: 1,
+            final_selection: FinalSelection::Auto,
+            state_interval: 1,
+            epsilon_greedy_rollout: 1.0,
+            min_visits: 0,
+            opening_book: true,
+            reward_per_move: false,
+            record_checkpoints: false,
+            expansion_order: ExpansionOrder::Last,
+            backup_rule: BackupRule::Average,
+            root_noise: None,
+            max_select_depth: None,
+            node_budget: None,
+            worker_seeding: WorkerSeeding::PerWorker,
+            tactical_scan: false,
+            exploration_schedule: None,
+        }
+    }
 }
 
 impl<G: Game> Mcts<G> {
-    pub fn new(iters: u32) -> Self {
+    pub fn with_config(config: MctsConfig) -> Self {
+        let rng = config
+            .seed
+            .map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
         Self {
             nodes: vec![],
-            iters,
+            config,
+            rng,
+            iterations: 0,
+            checkpoints: vec![],
+            noise: BTreeMap::new(),
+            spare_children: vec![],
+            spare_actions: vec![],
+            reused_buffers: 0,
         }
     }
 
     pub fn search(&mut self, state: &G) -> Option<Action> {
-        self.nodes.clear();
-        self.nodes.push(Node::new(state.clone(), None, None));
-        for _ in 0..self.iters {
-            let initial_reward = state.current_reward();
-            let node_idx = self.select();
-            let node_idx = self.expand(node_idx);
-            let game_result = self.simulate(node_idx);
-            self.backup(node_idx, game_result, initial_reward);
+        self.recycle_nodes();
+        self.iterations = 0;
+        self.checkpoints.clear();
+        // The root exists even when no search runs, so the tree accessors always have one
+        self.nodes
+            .push(Node::new(state.clone(), None, None, 0, true, vec![]));
+        if self.config.opening_book
+            && let Some(action) = state.book_move()
+        {
+            return Some(action);
+        }
+        if self.config.tactical_scan
+            && let Some(win) = state
+                .allowed_actions()
+                .into_iter()
+                .find(|&action| state.is_winning_action(action))
+        {
+            return Some(win);
+        }
+        if let [forced] = self.nodes[0].unvisited_actions[..] {
+            // Searching a forced move is wasted effort, so keep the tree at the root
+            return Some(forced);
+        }
+        if self.config.reduce_symmetries {
+            self.nodes[0].reduce_symmetric_actions();
+        }
+        if self.config.tactical_scan {
+            let blocking = blocking_actions(state);
+            if !blocking.is_empty() {
+                self.nodes[0]
+                    .unvisited_actions
+                    .retain(|action| blocking.contains(action));
+            }
+        }
+        self.sample_root_noise();
+        self.run(self.config.iters);
+        self.finish()
+    }
+
+    /// Play the hypothetical move `first` in `state`, then search the resulting position.
+    /// Returns the best reply and its value, if the search built a tree to estimate it,
+    /// or an error if `first` is illegal.
+    pub fn search_after(
+        &mut self,
+        state: &G,
+        first: Action,
+    ) -> Result<(Option<Action>, Option<f64>), &'static str> {
+        if !state.allowed_actions().contains(&first) {
+            return Err("Illegal first move");
+        }
+        let mut next = state.clone();
+        next.step(first)?;
+        let reply = self.search(&next);
+        Ok((reply, self.root_value()))
+    }
+
+    /// Search independent trees on `config.threads` threads, each seeded from the config
+    /// seed, and return the action with the most root visits across all trees.
+    /// This instance searches the first tree, which is retained.
+    pub fn search_parallel(&mut self, state: &G) -> Option<Action>
+    where
+        G: Send,
+    {
+        let threads = self.config.threads;
+        if threads <= 1 {
+            return self.search(state);
+        }
+        let master_seed = self.config.seed.unwrap_or_else(|| self.rng.u64(..));
+        let seeding = self.config.worker_seeding;
+        let seed = |index| match seeding {
+            WorkerSeeding::PerWorker => worker_seed(master_seed, index),
+            WorkerSeeding::Shared => SHARED_SEEDS.fetch_add(SEED_SPACING, Ordering::Relaxed),
+        };
+
+        thread::scope(|s| {
+            let workers: Vec<_> = (1..threads)
+                .map(|index| {
+                    let config = MctsConfig {
+                        seed: Some(seed(index)),
+                        ..self.config.clone()
+                    };
+                    let state = state.clone();
+                    s.spawn(move || {
+                        let mut agent = Mcts::with_config(config);
+                        agent.search(&state);
+                        agent.root_visits()
+                    })
+                })
+                .collect();
+
+            self.rng = fastrand::Rng::with_seed(seed(0));
+            let action = self.search(state);
+            if self.tree_size() <= 1 || self.root_proven() {
+                // Opening book or forced move, or a proven outcome the visits don't reflect
+                return action;
+            }
+            let mut visits: BTreeMap<Action, f64> = self.root_visits().into_iter().collect();
+            for worker in workers {
+                for (action, count) in worker.join().unwrap() {
+                    *visits.entry(action).or_default() += count;
+                }
+            }
+            visits
+                .into_iter()
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+                .map(|(action, _)| action)
+        })
+    }
+
+    /// Visits of each expanded root action
+    fn root_visits(&self) -> Vec<(Action, f64)> {
+        self.nodes.first().map_or_else(Vec::new, |root| {
+            root.children
+                .iter()
+                .map(|&idx| (self.nodes[idx].action.unwrap(), self.nodes[idx].visits))
+                .collect()
+        })
+    }
+
+    /// Run `additional` iterations on the tree retained from the last search
+    /// and return the updated best action.
+    pub fn continue_search(&mut self, additional: u32) -> Option<Action> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        self.run(additional);
+        self.finish()
+    }
+
+    /// Make the root child reached by `action` the new root, keeping its subtree.
+    /// Returns false, and drops the tree, if that child was never expanded.
+    pub fn advance_root(&mut self, action: Action) -> bool {
+        let Some(new_root) = self.nodes.first().and_then(|root| {
+            root.children
+                .iter()
+                .copied()
+                .find(|&idx| self.nodes[idx].action == Some(action))
+        }) else {
+            self.recycle_nodes();
+            return false;
+        };
+        let state = self.state(new_root).into_owned();
+        let root_depth = self.nodes[new_root].depth;
+
+        // Subtree nodes in breadth-first order, so parents come before their children
+        let mut order = vec![new_root];
+        let mut i = 0;
+        while i < order.len() {
+            order.extend(self.nodes[order[i]].children.iter().copied());
+            i += 1;
+        }
+        let mut new_index = vec![usize::MAX; self.nodes.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old] = new;
+        }
+
+        let mut old_nodes: Vec<Option<Node<G>>> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect();
+        for old in order {
+            let mut node = old_nodes[old].take().unwrap();
+            node.parent = node.parent.map(|parent| new_index[parent]);
+            for child in &mut node.children {
+                *child = new_index[*child];
+            }
+            node.depth -= root_depth;
+            self.nodes.push(node);
+        }
+        for node in old_nodes.into_iter().flatten() {
+            self.recycle(node);
+        }
+        let root = &mut self.nodes[0];
+        root.state = Some(state);
+        root.action = None;
+        root.parent = None;
+        self.checkpoints.clear();
+        self.sample_root_noise();
+        true
+    }
+
+    /// Drop all nodes, keeping their buffers for the next nodes
+    fn recycle_nodes(&mut self) {
+        let mut nodes = std::mem::take(&mut self.nodes);
+        for node in nodes.drain(..) {
+            self.recycle(node);
+        }
+        self.nodes = nodes;
+    }
+
+    /// Keep the allocated children and action buffers of a dropped node for reuse
+    fn recycle(&mut self, node: Node<G>) {
+        let mut children = node.children;
+        if children.capacity() > 0 {
+            children.clear();
+            self.spare_children.push(children);
+        }
+        let mut actions = node.unvisited_actions;
+        if actions.capacity() > 0 {
+            actions.clear();
+            self.spare_actions.push(actions);
+        }
+    }
+
+    /// Number of node buffers reused instead of allocated since the agent was created
+    #[allow(dead_code)]
+    pub fn reused_buffers(&self) -> usize {
+        self.reused_buffers
+    }
+
+    /// Draw new Dirichlet noise for the actions of the root
+    fn sample_root_noise(&mut self) {
+        self.noise.clear();
+        let Some(RootNoise { alpha, .. }) = self.config.root_noise else {
+            return;
+        };
+        let root = &self.nodes[0];
+        let actions: Vec<Action> = root
+            .children
+            .iter()
+            .map(|&idx| self.nodes[idx].action.unwrap())
+            .chain(root.unvisited_actions.iter().copied())
+            .collect();
+        let samples: Vec<f64> = actions
+            .iter()
+            .map(|_| sample_gamma(&mut self.rng, alpha))
+            .collect();
+        let total: f64 = samples.iter().sum();
+        if total > 0.0 {
+            self.noise = actions
+                .into_iter()
+                .zip(samples.into_iter().map(|sample| sample / total))
+                .collect();
+        }
+    }
+
+    /// Keep searching the retained tree, e.g. while the opponent thinks, until `stop` is set
+    /// or a full search worth of iterations has run
+    pub fn ponder(&mut self, stop: &AtomicBool) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut played = vec![];
+        for _ in 0..self.config.iters {
+            if stop.load(Ordering::Relaxed) || self.node_budget_spent() || self.root_proven() {
+                return;
+            }
+            self.iterate(0, &mut played);
+        }
+    }
+
+    fn run(&mut self, iters: u32) {
+        let mut played = vec![];
+        for _ in 0..iters {
+            if self.node_budget_spent() || self.root_proven() {
+                return;
+            }
+            self.iterate(0, &mut played);
+        }
+    }
+
+    /// Whether the outcome of the root is proven, so more iterations can't change the choice.
+    /// Selection would only pile visits onto one of the tied proven children.
+    fn root_proven(&self) -> bool {
+        self.nodes[0].proven.is_some()
+    }
+
+    /// Whether the tree has grown to `node_budget` nodes
+    fn node_budget_spent(&self) -> bool {
+        self.config
+            .node_budget
+            .is_some_and(|budget| self.nodes.len() >= budget.max(2))
+    }
+
+    /// Run one select-expand-simulate-backup iteration, selecting from node `start`
+    fn iterate(&mut self, start: usize, played: &mut Vec<(Player, Action)>) {
+        let root = self.root_state();
+        let initial_reward = root.current_reward();
+        let (min, max) = root.reward_range();
+        let reward_bounds = (initial_reward + min, initial_reward + max);
+        let node_idx = self.select(start);
+        let node_idx = if self.at_depth_limit(node_idx) {
+            node_idx
+        } else {
+            self.expand(node_idx)
+        };
+        played.clear();
+        let (mut game_result, rollout_moves) = self.simulate(node_idx, played);
+        if self.config.reward_per_move
+            && let GameResult::End(reward) = game_result
+        {
+            #[allow(clippy::cast_precision_loss)]
+            let moves = (self.nodes[node_idx].depth + rollout_moves).max(1) as f64;
+            game_result = GameResult::End(initial_reward + (reward - initial_reward) / moves);
+        }
+        self.backup(node_idx, game_result, reward_bounds, played);
+
+        self.iterations += 1;
+        if self.config.record_checkpoints && self.iterations.is_power_of_two() {
+            self.record_checkpoint();
+        }
+    }
+
+    /// Record the current best action and root value at the current iteration
+    fn record_checkpoint(&mut self) {
+        if self.checkpoints.last().map(|c| c.0) == Some(self.iterations) {
+            return;
+        }
+        if let (Some(idx), Some(value)) = (self.chosen_child(), self.root_value()) {
+            let action = self.nodes[idx].action.unwrap();
+            self.checkpoints.push((self.iterations, action, value));
+        }
+    }
+
+    /// Apply the final selection rule and return the chosen action
+    fn finish(&mut self) -> Option<Action> {
+        if let FinalSelection::RobustMax { max_extra_iters } = self.config.final_selection {
+            self.refine_robust(max_extra_iters);
+        }
+        if self.config.record_checkpoints {
+            self.record_checkpoint();
         }
         self.best_action()
     }
 
-    /// Walk the tree to find the first node that is either terminal or has unvisited actions.
-    /// If a given node is neither, walk to the child with highest UCB1 score.
-    fn select(&self) -> usize {
-        let mut idx = 0;
+    /// Search the most visited and the highest valued root children until they agree
+    fn refine_robust(&mut self, max_extra_iters: u32) {
+        let rule = self.config.backup_rule;
+        let mut played = vec![];
+        for _ in 0..max_extra_iters {
+            if self.root_proven() {
+                return;
+            }
+            let root = &self.nodes[0];
+            let by_visits = root.children.iter().copied().max_by(|a, b| {
+                self.nodes[*a]
+                    .visits
+                    .partial_cmp(&self.nodes[*b].visits)
+                    .unwrap()
+            });
+            let by_value = root.children.iter().copied().max_by(|a, b| {
+                self.nodes[*a]
+                    .value(rule)
+                    .partial_cmp(&self.nodes[*b].value(rule))
+                    .unwrap()
+            });
+            let (Some(by_visits), Some(by_value)) = (by_visits, by_value) else {
+                return;
+            };
+            if by_visits == by_value {
+                return;
+            }
+
+            let parent_visits = root.visits;
+            let exploration = self.exploration();
+            let disputed = if self.nodes[by_visits].ucb1(parent_visits, exploration, rule)
+                >= self.nodes[by_value].ucb1(parent_visits, exploration, rule)
+            {
+                by_visits
+            } else {
+                by_value
+            };
+            self.iterate(disputed, &mut played);
+        }
+    }
+
+    /// Whether the node is at `max_select_depth`, so the search neither descends nor expands
+    /// below it
+    fn at_depth_limit(&self, idx: usize) -> bool {
+        self.config
+            .max_select_depth
+            .is_some_and(|max| self.nodes[idx].depth >= max.max(1))
+    }
+
+    /// Walk the tree from `start` to find the first node that is either terminal or has
+    /// unvisited actions. If a given node is neither, walk to the child with highest UCB1 score.
+    /// Nodes without children, whose every action the game rejected, also end the walk.
+    fn select(&self, start: usize) -> usize {
+        let mut idx = start;
 
         loop {
             let node = &self.nodes[idx];
 
-            if node.is_terminal() || node.has_unvisited_actions() {
+            if node.is_terminal()
+                || node.has_unvisited_actions()
+                || node.children.is_empty()
+                || self.at_depth_limit(idx)
+            {
                 return idx;
             }
 
@@ -44,77 +665,471 @@ impl<G: Game> Mcts<G> {
 
     /// Expand a nonterminal node with unvisited actions.
     /// If the node is terminal or has no unvisited actions, return the node itself.
+    /// Actions the game rejects are dropped with a warning, instead of failing the search.
     fn expand(&mut self, node_idx: usize) -> usize {
-        let node = &mut self.nodes[node_idx];
-
-        if node.is_terminal() {
+        if self.nodes[node_idx].is_terminal() {
             return node_idx;
         }
 
-        let Some(action) = node.unvisited_actions.pop() else {
-            return node_idx;
+        let (action, actor, state) = loop {
+            let node = &mut self.nodes[node_idx];
+            if node.unvisited_actions.is_empty() {
+                return node_idx;
+            }
+            let action = match self.config.expansion_order {
+                ExpansionOrder::Last => node.unvisited_actions.pop().unwrap(),
+                ExpansionOrder::First => node.unvisited_actions.remove(0),
+                ExpansionOrder::Random => {
+                    let i = self.rng.usize(..node.unvisited_actions.len());
+                    node.unvisited_actions.swap_remove(i)
+                }
+            };
+            let mut state = self.state(node_idx).into_owned();
+            let actor = state.current_player();
+            match state.step(action) {
+                Ok(()) => break (action, actor, state),
+                Err(e) => log::warn!("Dropping action {action} rejected by the game: {e}"),
+            }
         };
 
-        let mut state = node.state.clone();
-        state.step(action).unwrap();
-        let child_node = Node::new(state, Some(action), Some(node_idx));
+        let depth = self.nodes[node_idx].depth + 1;
+        let keep_state = depth.is_multiple_of(self.config.state_interval.max(1));
+        let actions = match self.spare_actions.pop() {
+            Some(actions) => {
+                self.reused_buffers += 1;
+                actions
+            }
+            None => vec![],
+        };
+        let mut child_node = Node::new(
+            state,
+            Some((actor, action)),
+            Some(node_idx),
+            depth,
+            keep_state,
+            actions,
+        );
+        if let Some(children) = self.spare_children.pop() {
+            child_node.children = children;
+            self.reused_buffers += 1;
+        }
         let child_idx = self.nodes.len();
         self.nodes.push(child_node);
         self.nodes[node_idx].children.push(child_idx);
         child_idx
     }
 
-    /// Simulate the rest of the game with random actions
-    fn simulate(&self, node_idx: usize) -> GameResult {
-        let mut game = self.nodes[node_idx].state.clone();
+    /// Simulate the rest of the game with random actions, returning the result and the
+    /// number of moves played. With RAVE enabled, the actions played are recorded in `played`.
+    fn simulate(
+        &mut self,
+        node_idx: usize,
+        played: &mut Vec<(Player, Action)>,
+    ) -> (GameResult, usize) {
+        if let Some(game_result) = self.nodes[node_idx].result {
+            return (game_result, 0);
+        }
+        let observer = self.root_state().current_player();
+        // The state borrows the tree, so the RNG is lent out while hidden state is drawn
+        let mut rng = std::mem::replace(&mut self.rng, fastrand::Rng::with_seed(0));
+        let mut game = self.state(node_idx).observable_clone(observer, &mut rng);
+        self.rng = rng;
+        let mut moves = 0;
         loop {
-            if let Some(game_result) = game.result() {
-                return game_result;
+            if let Some(game_result) = game.result_with_limit() {
+                return (game_result, moves);
             }
-            let actions = game.allowed_actions();
-            let action = actions[fastrand::usize(0..actions.len())];
-            game.step(action).unwrap();
+            let epsilon = self.config.epsilon_greedy_rollout;
+            let greedy = epsilon < 1.0 && self.rng.f64() >= epsilon;
+            let heuristic = if greedy { game.heuristic_move() } else { None };
+            let mut action = heuristic.unwrap_or_else(|| game.rollout_action(&mut self.rng));
+            let actor = game.current_player();
+            if let Err(e) = game.step(action) {
+                log::warn!("Rollout replacing action {action} rejected by the game: {e}");
+                let rejected = action;
+                let Some((accepted, next)) = game
+                    .allowed_actions()
+                    .into_iter()
+                    .filter(|&other| other != rejected)
+                    .find_map(|other| {
+                        let mut next = game.clone();
+                        next.step(other).ok().map(|()| (other, next))
+                    })
+                else {
+                    // The game accepts no action, so score the position as a draw
+                    return (GameResult::Draw, moves);
+                };
+                action = accepted;
+                game = next;
+            }
+            if self.config.rave_bias.is_some() {
+                played.push((actor, action));
+            }
+            moves += 1;
         }
     }
 
-    /// Back up visits & rewards
-    fn backup(&mut self, node_idx: usize, game_result: GameResult, initial_reward: f64) {
+    /// Back up visits & rewards.
+    /// With RAVE enabled, also credit the AMAF statistics of every sibling whose action
+    /// was later played by the same player, in the tree or in the rollout.
+    fn backup(
+        &mut self,
+        node_idx: usize,
+        game_result: GameResult,
+        reward_bounds: (f64, f64),
+        played: &mut Vec<(Player, Action)>,
+    ) {
+        let rave = self.config.rave_bias.is_some();
         let mut current = Some(node_idx);
         while let Some(idx) = current {
+            if rave {
+                for i in 0..self.nodes[idx].children.len() {
+                    let child_idx = self.nodes[idx].children[i];
+                    let child = &mut self.nodes[child_idx];
+                    let actor = child.actor;
+                    if played.contains(&(actor, child.action.unwrap())) {
+                        child.amaf_visits += 1.0;
+                        child.amaf_reward += reward_for(game_result, actor, reward_bounds);
+                    }
+                }
+            }
+
             let node = &mut self.nodes[idx];
+            let reward = reward_for(game_result, node.actor, reward_bounds);
             node.visits += 1.0;
-            node.reward += match game_result {
-                GameResult::Win(player) => f64::from(player == node.actor()),
-                GameResult::Draw => 0.5,
-                GameResult::End(reward) => reward as f64 - initial_reward,
-            };
+            node.reward += reward;
+            node.max_reward = node.max_reward.max(reward);
+            if rave && let Some(action) = node.action {
+                played.push((node.actor, action));
+            }
             current = node.parent;
+            self.update_proof(idx);
+        }
+    }
+
+    /// Prove a node from its children: the player to move wins if any of their moves is a
+    /// proven win, and loses if all their moves are expanded and proven losses
+    fn update_proof(&mut self, idx: usize) {
+        let node = &self.nodes[idx];
+        let Some(&first_child) = node.children.first() else {
+            return;
+        };
+        if node.proven.is_some() {
+            return;
+        }
+        let mover = self.nodes[first_child].actor;
+        let children = node.children.iter().map(|&child| &self.nodes[child]);
+        let wins = children
+            .clone()
+            .filter(|child| child.proven == Some(Proof::Win));
+        let (outcome, length) = if let Some(fastest) = wins.map(|child| child.proof_length).min() {
+            (Proof::Win, fastest)
+        } else if !node.has_unvisited_actions()
+            && children
+                .clone()
+                .all(|child| child.proven == Some(Proof::Loss))
+        {
+            let slowest = children.map(|child| child.proof_length).max().unwrap_or(0);
+            (Proof::Loss, slowest)
+        } else {
+            return;
+        };
+        let actor = node.actor;
+        let node = &mut self.nodes[idx];
+        node.proven = Some(if actor == mover {
+            outcome
+        } else {
+            outcome.for_opponent()
+        });
+        node.proof_length = length + 1;
+    }
+
+    /// State of the root node, which is always kept
+    fn root_state(&self) -> &G {
+        self.nodes[0].state.as_ref().unwrap()
+    }
+
+    /// State of a node, rebuilt from its closest kept ancestor if it wasn't kept
+    fn state(&self, node_idx: usize) -> Cow<'_, G> {
+        let mut actions = vec![];
+        let mut idx = node_idx;
+        loop {
+            let node = &self.nodes[idx];
+            if let Some(state) = &node.state {
+                if actions.is_empty() {
+                    return Cow::Borrowed(state);
+                }
+                let mut state = state.clone();
+                for action in actions.into_iter().rev() {
+                    state.step(action).unwrap();
+                }
+                return Cow::Owned(state);
+            }
+            actions.push(node.action.unwrap());
+            idx = node.parent.unwrap();
         }
     }
 
     /// Select the "best" action by finding the root node child with the most visits.
     /// As the number of MCTS iterations increases, this value approaches the optimal decision.
+    /// A proven win is always chosen, and proven losses only if nothing else is left.
     fn best_action(&self) -> Option<Action> {
-        self.nodes[0]
+        self.chosen_child().and_then(|idx| self.nodes[idx].action)
+    }
+
+    /// Index of the root child `best_action` picks, if the root has children.
+    /// The fastest proven win is always chosen. When every move is a proven loss, the one
+    /// holding out longest is chosen, giving a fallible opponent the most chances to err.
+    fn chosen_child(&self) -> Option<usize> {
+        let children = &self.nodes.first()?.children;
+        if let Some(win) = children
+            .iter()
+            .copied()
+            .filter(|&idx| self.nodes[idx].proven == Some(Proof::Win))
+            .min_by_key(|&idx| self.nodes[idx].proof_length)
+        {
+            return Some(win);
+        }
+        let by_value = match self.config.final_selection {
+            FinalSelection::MaxValue => true,
+            FinalSelection::Auto => self.root_state().num_players() == 1,
+            FinalSelection::MostVisits | FinalSelection::RobustMax { .. } => false,
+        };
+        let rule = self.config.backup_rule;
+        children.iter().copied().max_by(|&a, &b| {
+            let rank = |node: &Node<G>| {
+                let lost = node.proven == Some(Proof::Loss);
+                // Proven losses get no visits once proven, so their value breaks ties
+                let strength = if by_value || lost {
+                    node.value(rule)
+                } else {
+                    node.visits
+                };
+                let holdout = if lost { node.proof_length } else { 0 };
+                (!lost, holdout, strength)
+            };
+            rank(&self.nodes[a])
+                .partial_cmp(&rank(&self.nodes[b]))
+                .unwrap()
+        })
+    }
+
+    /// Most visited reply to the chosen root action, i.e. the move the search expects next
+    pub fn expected_reply(&self) -> Option<Action> {
+        let chosen = &self.nodes[self.chosen_child()?];
+        chosen
             .children
             .iter()
-            .map(|idx| {
-                let a = &self.nodes[*idx];
-                println!("{} visits for {:?}", a.visits, a.action.unwrap());
-                a
-            })
+            .map(|&idx| &self.nodes[idx])
             .max_by(|a, b| a.visits.partial_cmp(&b.visits).unwrap())
-            .unwrap()
-            .action
+            .and_then(|reply| reply.action)
+    }
+
+    /// Search `state`, ignoring the opening book and tactical scan, and return each expanded
+    /// root action with its value and visits, the chosen action first and the rest by value
+    pub fn analyze(&mut self, state: &G) -> Vec<(Action, f64, f64)> {
+        let opening_book = std::mem::replace(&mut self.config.opening_book, false);
+        let tactical_scan = std::mem::replace(&mut self.config.tactical_scan, false);
+        self.search(state);
+        self.config.opening_book = opening_book;
+        self.config.tactical_scan = tactical_scan;
+        let mut analysis: Vec<(Action, f64, f64)> =
+            self.nodes.first().map_or_else(Vec::new, |root| {
+                root.children
+                    .iter()
+                    .map(|&idx| {
+                        let child = &self.nodes[idx];
+                        let value = child.value(self.config.backup_rule);
+                        (child.action.unwrap(), value, child.visits)
+                    })
+                    .collect()
+            });
+        analysis.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        // The chosen action leads even when a proof overrides the estimated values
+        let chosen = self.chosen_child().and_then(|idx| self.nodes[idx].action);
+        if let Some(pos) = analysis
+            .iter()
+            .position(|&(action, ..)| Some(action) == chosen)
+        {
+            analysis[..=pos].rotate_right(1);
+        }
+        analysis
+    }
+
+    /// Search `state`, ignoring the opening book and tactical scan, and return how much less
+    /// mean reward than the best action `played` gets for the player to move,
+    /// or `None` if the search never tried it
+    pub fn value_gap(&mut self, state: &G, played: Action) -> Option<f64> {
+        let opening_book = std::mem::replace(&mut self.config.opening_book, false);
+        let tactical_scan = std::mem::replace(&mut self.config.tactical_scan, false);
+        let action = self.search(state);
+        self.config.opening_book = opening_book;
+        self.config.tactical_scan = tactical_scan;
+        if self.tree_size() <= 1 {
+            // A forced move is as good as the best one
+            return (action == Some(played)).then_some(0.0);
+        }
+        let best = self.root_value()?;
+        let root = self.nodes.first()?;
+        let played = root
+            .children
+            .iter()
+            .map(|&idx| &self.nodes[idx])
+            .find(|child| child.action == Some(played))?;
+        Some((best - played.value(self.config.backup_rule)).max(0.0))
+    }
+
+    /// Mean reward of the chosen root action for the player to move at the root
+    pub fn root_value(&self) -> Option<f64> {
+        self.chosen_child()
+            .map(|idx| self.nodes[idx].value(self.config.backup_rule))
+    }
+
+    /// Value lost by each expanded root action relative to the highest valued one,
+    /// which has regret 0
+    pub fn action_regrets(&self) -> Vec<(Action, f64)> {
+        let rule = self.config.backup_rule;
+        let values: Vec<(Action, f64)> = self.nodes.first().map_or_else(Vec::new, |root| {
+            root.children
+                .iter()
+                .map(|&idx| (self.nodes[idx].action.unwrap(), self.nodes[idx].value(rule)))
+                .collect()
+        });
+        let best = values
+            .iter()
+            .map(|&(_, value)| value)
+            .fold(f64::NEG_INFINITY, f64::max);
+        values
+            .into_iter()
+            .map(|(action, value)| (action, best - value))
+            .collect()
+    }
+
+    /// Checkpoints recorded by the last search, when `record_checkpoints` is set
+    pub fn checkpoints(&self) -> &[(u32, Action, f64)] {
+        &self.checkpoints
+    }
+
+    /// Number of visits of the root child reached by `action`, or 0 if it was never expanded
+    pub fn action_visits(&self, action: Action) -> f64 {
+        self.nodes.first().map_or(0.0, |root| {
+            root.children
+                .iter()
+                .map(|idx| &self.nodes[*idx])
+                .find(|child| child.action == Some(action))
+                .map_or(0.0, |child| child.visits)
+        })
+    }
+
+    /// Legal root actions the last search never tried
+    pub fn unexpanded_root_actions(&self) -> Vec<Action> {
+        self.nodes
+            .first()
+            .map_or_else(Vec::new, |root| root.unvisited_actions.clone())
+    }
+
+    /// Root visit distribution over the whole action space, as a policy training target.
+    /// Illegal and unexpanded actions get 0.
+    pub fn policy_target(&self, action_space_size: usize) -> Vec<f32> {
+        let mut policy = vec![0.0; action_space_size];
+        let Some(root) = self.nodes.first() else {
+            return policy;
+        };
+        let total: f64 = root
+            .children
+            .iter()
+            .map(|&idx| self.nodes[idx].visits)
+            .sum();
+        if total == 0.0 {
+            return policy;
+        }
+        for &idx in &root.children {
+            let child = &self.nodes[idx];
+            #[allow(clippy::cast_possible_truncation)]
+            let share = (child.visits / total) as f32;
+            policy[child.action.unwrap()] = share;
+        }
+        policy
+    }
+
+    /// Number of nodes in the tree of the last search
+    pub fn tree_size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The whole tree of the last search, one line per node in index order with its parent,
+    /// action, visits and reward. Equal snapshots mean identical searches.
+    pub fn tree_snapshot(&self) -> String {
+        let field = |value: Option<usize>| value.map_or_else(|| "-".to_string(), |v| v.to_string());
+        let mut snapshot = String::new();
+        for (idx, node) in self.nodes.iter().enumerate() {
+            writeln!(
+                snapshot,
+                "{idx} {} {} {} {}",
+                field(node.parent),
+                field(node.action),
+                node.visits,
+                node.reward
+            )
+            .unwrap();
+        }
+        snapshot
+    }
+
+    /// Distance from the root to the deepest node of the last search
+    pub fn max_depth(&self) -> usize {
+        self.nodes.iter().map(|node| node.depth).max().unwrap_or(0)
+    }
+
+    /// Scale of the UCB exploration term at the current iteration of the search
+    fn exploration(&self) -> f64 {
+        let Some(ExplorationSchedule { start, end, decay }) = self.config.exploration_schedule
+        else {
+            return 1.0;
+        };
+        let progress = (f64::from(self.iterations) / f64::from(self.config.iters.max(1))).min(1.0);
+        match decay {
+            Decay::Exponential if start > 0.0 && end > 0.0 => start * (end / start).powf(progress),
+            Decay::Linear | Decay::Exponential => start + (end - start) * progress,
+        }
     }
 
     /// Select the child node with the highest UCB1 score
     fn best_child(&self, idx: usize) -> usize {
         let node = &self.nodes[idx];
         let visits = node.visits;
+        let exploration = self.exploration();
+        let noise = if idx == 0 {
+            self.config.root_noise
+        } else {
+            None
+        };
         node.children
             .iter()
-            .map(|idx| (*idx, self.nodes[*idx].ucb1(visits)))
+            .map(|idx| {
+                let child = &self.nodes[*idx];
+                let score = match self.config.rave_bias {
+                    _ if child.proven == Some(Proof::Win) => f64::INFINITY,
+                    _ if child.proven == Some(Proof::Loss) => f64::NEG_INFINITY,
+                    _ if child.visits < f64::from(self.config.min_visits) => f64::INFINITY,
+                    Some(bias) => {
+                        child.rave_ucb1(visits, exploration, bias, self.config.backup_rule)
+                    }
+                    None => child.ucb1(visits, exploration, self.config.backup_rule),
+                };
+                let score = match noise {
+                    Some(RootNoise { epsilon, .. }) => {
+                        // A bonus fading with visits, like an AlphaZero prior. Noise sums to 1,
+                        // so it is scaled to average 1 per action.
+                        #[allow(clippy::cast_precision_loss)]
+                        let scale = self.noise.len() as f64;
+                        let noise = self.noise.get(&child.action.unwrap()).unwrap_or(&0.0);
+                        score + epsilon * scale * noise * visits.sqrt() / (1.0 + child.visits)
+                    }
+                    None => score,
+                };
+                (*idx, score)
+            })
             .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
             .unwrap()
             .0
@@ -122,45 +1137,1169 @@ impl<G: Game> Mcts<G> {
 }
 
 struct Node<G> {
-    state: G,
+    /// Game state, unless it is rebuilt on demand
+    state: Option<G>,
+    /// Player who took the node action, as recorded when it was taken
+    actor: Player,
     action: Option<Action>,
     parent: Option<usize>,
+    depth: usize,
     children: Vec<usize>,
     visits: f64,
     reward: f64,
+    /// Best reward of a rollout through the node, for the `Max` backup rule
+    max_reward: f64,
+    amaf_visits: f64,
+    amaf_reward: f64,
     unvisited_actions: Vec<Action>,
+    /// Cached result of `state`, so terminal leaves need no rollout
+    result: Option<GameResult>,
+    /// Outcome for the actor, once proven from terminal results
+    proven: Option<Proof>,
+    /// Moves from a proven node to the end of the game, with the winner finishing as soon
+    /// and the loser holding out as long as they can
+    proof_length: usize,
 }
 
 impl<G: Game> Node<G> {
-    fn new(state: G, action: Option<Action>, parent: Option<usize>) -> Self {
-        let unvisited_actions = state.allowed_actions();
+    /// Create a node for `state`, reached by the player and action in `move_taken`
+    /// unless it is the root. Its unvisited actions are filled into the empty `actions` buffer.
+    fn new(
+        state: G,
+        move_taken: Option<(Player, Action)>,
+        parent: Option<usize>,
+        depth: usize,
+        keep_state: bool,
+        mut actions: Vec<Action>,
+    ) -> Self {
+        actions.extend(state.allowed_actions());
+        let unvisited_actions = actions;
+        let result = state.result_with_limit();
+        // The root took no action, so it is credited to the opponent of the player to move
+        let actor =
+            move_taken.map_or_else(|| state.current_player().opponent(), |(actor, _)| actor);
+        let action = move_taken.map(|(_, action)| action);
+        let proven = match result {
+            Some(GameResult::Win(player)) if player == actor => Some(Proof::Win),
+            Some(GameResult::Win(_)) => Some(Proof::Loss),
+            _ => None,
+        };
         Node {
-            state,
+            state: keep_state.then_some(state),
+            actor,
             action,
             parent,
+            depth,
             children: vec![],
             visits: 0.0,
             reward: 0.0,
+            max_reward: 0.0,
+            amaf_visits: 0.0,
+            amaf_reward: 0.0,
             unvisited_actions,
+            result,
+            proven,
+            proof_length: 0,
         }
     }
 
-    /// Player responsible for the node action
-    fn actor(&self) -> Player {
-        self.state.current_player().opponent()
+    fn is_terminal(&self) -> bool {
+        self.result.is_some()
     }
 
-    fn is_terminal(&self) -> bool {
-        self.state.result().is_some()
+    /// Keep a single representative per class of symmetric unvisited actions
+    fn reduce_symmetric_actions(&mut self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let symmetries = state.symmetries();
+        self.unvisited_actions
+            .retain(|&action| symmetries.iter().all(|perm| perm[action] >= action));
     }
 
     fn has_unvisited_actions(&self) -> bool {
         !self.unvisited_actions.is_empty()
     }
 
-    fn ucb1(&self, parent_visits: f64) -> f64 {
-        let r_exploit = self.reward / self.visits;
-        let r_explore = (2.0 * parent_visits.ln() / self.visits).sqrt();
+    /// Value of the node for its actor under the backup rule
+    fn value(&self, rule: BackupRule) -> f64 {
+        match rule {
+            BackupRule::Average => self.reward / self.visits,
+            BackupRule::Max => self.max_reward,
+        }
+    }
+
+    fn ucb1(&self, parent_visits: f64, exploration: f64, rule: BackupRule) -> f64 {
+        let r_exploit = self.value(rule);
+        let r_explore = exploration * (2.0 * parent_visits.ln() / self.visits).sqrt();
         r_exploit + r_explore
     }
+
+    /// UCB1 with the exploitation term blended with AMAF statistics
+    fn rave_ucb1(&self, parent_visits: f64, exploration: f64, bias: f64, rule: BackupRule) -> f64 {
+        if self.amaf_visits == 0.0 {
+            return self.ucb1(parent_visits, exploration, rule);
+        }
+        let beta = self.amaf_visits
+            / (self.visits + self.amaf_visits + bias * self.visits * self.amaf_visits);
+        let r_exploit =
+            (1.0 - beta) * self.value(rule) + beta * self.amaf_reward / self.amaf_visits;
+        let r_explore = exploration * (2.0 * parent_visits.ln() / self.visits).sqrt();
+        r_exploit + r_explore
+    }
+}
+
+/// Actions after which the opponent has no immediate win.
+/// All actions if the opponent threatens none, and none if every action loses.
+fn blocking_actions<G: Game>(state: &G) -> Vec<Action> {
+    let player = state.current_player();
+    state
+        .successors()
+        .into_iter()
+        .filter(|(_, next)| {
+            next.current_player() == player
+                || next.result_with_limit().is_some()
+                || !next
+                    .allowed_actions()
+                    .into_iter()
+                    .any(|action| next.is_winning_action(action))
+        })
+        .map(|(action, _)| action)
+        .collect()
+}
+
+/// Seed of worker `index` of a parallel search: distinct per worker, fixed by the master seed
+fn worker_seed(master_seed: u64, index: usize) -> u64 {
+    master_seed.wrapping_add((index as u64).wrapping_mul(SEED_SPACING))
+}
+
+/// Sample of a Gamma(`shape`, 1) distribution (Marsaglia and Tsang)
+fn sample_gamma(rng: &mut fastrand::Rng, shape: f64) -> f64 {
+    if shape < 1.0 {
+        // Boost the shape above 1 and scale the sample back down
+        let u = 1.0 - rng.f64();
+        return sample_gamma(rng, shape + 1.0) * u.powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        // Standard normal sample (Box-Muller)
+        let normal =
+            (-2.0 * (1.0 - rng.f64()).ln()).sqrt() * (std::f64::consts::TAU * rng.f64()).cos();
+        let v = (1.0 + c * normal).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let uniform = 1.0 - rng.f64();
+        if uniform.ln() < 0.5 * normal * normal + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+/// Reward credited to `actor` for a finished game, in [0, 1].
+/// `End` rewards are mapped from `reward_bounds` into that range.
+fn reward_for(game_result: GameResult, actor: Player, reward_bounds: (f64, f64)) -> f64 {
+    let (min, max) = reward_bounds;
+    match game_result {
+        GameResult::Win(player) => f64::from(player == actor),
+        GameResult::Draw => 0.5,
+        GameResult::End(reward) => ((reward - min) / (max - min)).clamp(0.0, 1.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{connect4::Connect4, tetris::Tetris, tictactoe::TicTacToe};
+
+    fn seeded(seed: u64) -> MctsConfig {
+        MctsConfig {
+            iters: 2_000,
+            seed: Some(seed),
+            opening_book: false,
+            ..MctsConfig::default()
+        }
+    }
+
+    /// X moves twice in a row, then O once, and X wins if their second move is 1
+    #[derive(Clone, Debug, Default)]
+    struct ExtraTurn {
+        moves: Vec<Action>,
+        /// Also list action 2, which `step` rejects, like an inconsistently loaded game
+        phantom_action: bool,
+    }
+
+    impl Game for ExtraTurn {
+        fn print_instructions(&self) {}
+
+        fn result(&self) -> Option<GameResult> {
+            (self.moves.len() == 3).then(|| {
+                GameResult::Win(if self.moves[1] == 1 {
+                    Player::X
+                } else {
+                    Player::O
+                })
+            })
+        }
+
+        fn allowed_actions(&self) -> Vec<Action> {
+            if self.moves.len() < 3 {
+                if self.phantom_action {
+                    vec![0, 1, 2]
+                } else {
+                    vec![0, 1]
+                }
+            } else {
+                vec![]
+            }
+        }
+
+        fn current_player(&self) -> Player {
+            if self.moves.len() < 2 {
+                Player::X
+            } else {
+                Player::O
+            }
+        }
+
+        fn next_player(&self) -> Player {
+            if self.moves.is_empty() {
+                Player::X
+            } else {
+                Player::O
+            }
+        }
+
+        fn step(&mut self, action: Action) -> Result<(), &'static str> {
+            if action > 1 || self.moves.len() == 3 {
+                return Err("Invalid move");
+            }
+            self.moves.push(action);
+            Ok(())
+        }
+
+        fn ply(&self) -> usize {
+            self.moves.len()
+        }
+
+        fn last_action(&self) -> Option<Action> {
+            self.moves.last().copied()
+        }
+
+        fn material(&self) -> Vec<usize> {
+            vec![]
+        }
+
+        fn validate(&self) -> Result<(), &'static str> {
+            Ok(())
+        }
+
+        fn state_key(&self) -> String {
+            format!("{:?}", self.moves)
+        }
+
+        fn to_tensor(&self) -> (Vec<f32>, Vec<usize>) {
+            (vec![], vec![0])
+        }
+
+        fn action_space_size(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    fn extra_turns_credit_the_player_who_moved() {
+        let game = play(ExtraTurn::default(), &[0]);
+        let mut agent = Mcts::with_config(seeded(42));
+        let analysis = agent.analyze(&game);
+        assert_eq!(analysis[0].0, 1);
+        assert!((analysis[0].1 - 1.0).abs() < 1e-9);
+        assert!(analysis[1].1.abs() < 1e-9);
+        assert_eq!(game.turn_parity(&play(game.clone(), &[1])), -1);
+        assert_eq!(ExtraTurn::default().turn_parity(&game), 1);
+        assert_eq!(crate::solver::solve(&ExtraTurn::default()), 1);
+    }
+
+    #[test]
+    fn actions_the_game_rejects_are_skipped() {
+        let game = ExtraTurn {
+            phantom_action: true,
+            ..ExtraTurn::default()
+        };
+        for expansion_order in [ExpansionOrder::First, ExpansionOrder::Last] {
+            let mut agent = Mcts::with_config(MctsConfig {
+                expansion_order,
+                ..seeded(42)
+            });
+            assert!(agent.search(&game).is_some_and(|action| action < 2));
+            assert_eq!(agent.nodes[0].children.len(), 2);
+        }
+        let actions: Vec<Action> = game.successors().iter().map(|&(a, _)| a).collect();
+        assert_eq!(actions, [0, 1]);
+    }
+
+    fn play<G: Game>(mut game: G, moves: &[Action]) -> G {
+        for &action in moves {
+            game.step(action).unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn search_stops_once_the_root_is_proven() {
+        // X threatens 0-4-8 and forks after any block, so every move of O is proven lost
+        let game = play(TicTacToe::default(), &[0, 1, 4]);
+        let mut agent = Mcts::with_config(seeded(42));
+        agent.search(&game);
+        assert!(agent.root_proven());
+        assert!(agent.iterations < agent.config.iters);
+        let visits = agent.nodes[0].visits;
+        agent.continue_search(1_000);
+        assert!((agent.nodes[0].visits - visits).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn analyze_after_a_tactical_win() {
+        let game = play(Connect4::default(), &[0, 0, 1, 1, 2, 6]);
+        let config = MctsConfig {
+            tactical_scan: true,
+            ..seeded(42)
+        };
+        let mut agent = Mcts::with_config(config);
+        assert_eq!(agent.search(&game), Some(3));
+        assert_eq!(agent.tree_size(), 1);
+        assert_eq!(agent.root_value(), None);
+        let analysis = agent.analyze(&game);
+        assert_eq!(analysis.first().map(|&(action, ..)| action), Some(3));
+    }
+
+    #[test]
+    fn book_moves_keep_a_root() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            opening_book: true,
+            ..seeded(42)
+        });
+        let game = Connect4::default();
+        assert_eq!(agent.search(&game), game.book_move());
+        assert_eq!(agent.tree_size(), 1);
+        assert!(agent.analyze(&game).len() > 1);
+    }
+
+    #[test]
+    fn tiny_limits_still_expand_the_root() {
+        for (node_budget, max_select_depth) in [(Some(0), None), (Some(1), None), (None, Some(0))] {
+            let mut agent = Mcts::with_config(MctsConfig {
+                max_select_depth,
+                node_budget,
+                ..seeded(42)
+            });
+            assert!(agent.search(&TicTacToe::default()).is_some());
+            assert!(agent.tree_size() > 1);
+        }
+    }
+
+    #[test]
+    fn seeded_tetris_searches_are_reproducible() {
+        let snapshot = || {
+            let mut agent = Mcts::with_config(MctsConfig {
+                iters: 300,
+                ..seeded(7)
+            });
+            agent.search(&Tetris::with_seed(7));
+            agent.tree_snapshot()
+        };
+        assert_eq!(snapshot(), snapshot());
+    }
+
+    #[test]
+    fn exponential_exploration_without_positive_scales_decays_linearly() {
+        let schedule = ExplorationSchedule {
+            start: 0.0,
+            end: 0.5,
+            decay: Decay::Exponential,
+        };
+        let mut agent = Mcts::with_config(MctsConfig {
+            exploration_schedule: Some(schedule),
+            ..seeded(42)
+        });
+        assert!(agent.search(&TicTacToe::default()).is_some());
+        assert!((agent.exploration() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn single_player_games_pick_the_highest_valued_move() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 300,
+            ..seeded(7)
+        });
+        let action = agent.search(&Tetris::with_seed(7)).unwrap();
+        let regrets = agent.action_regrets();
+        assert!(regrets.contains(&(action, 0.0)));
+    }
+
+    #[test]
+    fn only_the_best_action_has_no_regret() {
+        let mut agent = Mcts::with_config(seeded(42));
+        agent.search(&play(Connect4::default(), &[3, 3]));
+        let regrets = agent.action_regrets();
+        assert_eq!(regrets.len(), 7);
+        assert_eq!(
+            regrets.iter().filter(|&&(_, regret)| regret == 0.0).count(),
+            1
+        );
+        assert!(regrets.iter().all(|&(_, regret)| regret >= 0.0));
+    }
+
+    #[test]
+    fn lost_position_holds_out_longest() {
+        // Blocking at 8 only loses after the fork, every other move loses at once
+        let game = play(TicTacToe::default(), &[0, 1, 4]);
+        for seed in [42, 1, 2, 3] {
+            let mut agent = Mcts::with_config(seeded(seed));
+            assert_eq!(agent.search(&game), Some(8), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn won_position_takes_the_fastest_win() {
+        // X wins at once at 2, and two moves later after the fork at 6
+        let game = play(TicTacToe::default(), &[0, 3, 4, 8, 1, 7]);
+        for seed in [42, 1, 2, 3] {
+            let mut agent = Mcts::with_config(seeded(seed));
+            let action = agent.search(&game).unwrap();
+            assert!(
+                game.is_winning_action(action),
+                "seed {seed} played {action}"
+            );
+        }
+    }
+
+    /// Players take turns forever, so only the ply limit of 4 ends the game
+    #[derive(Clone, Debug, Default)]
+    struct Endless {
+        ply: usize,
+    }
+
+    impl Game for Endless {
+        fn print_instructions(&self) {}
+
+        fn result(&self) -> Option<GameResult> {
+            None
+        }
+
+        fn allowed_actions(&self) -> Vec<Action> {
+            vec![0, 1]
+        }
+
+        fn current_player(&self) -> Player {
+            if self.ply.is_multiple_of(2) {
+                Player::X
+            } else {
+                Player::O
+            }
+        }
+
+        fn step(&mut self, _action: Action) -> Result<(), &'static str> {
+            self.ply += 1;
+            Ok(())
+        }
+
+        fn ply(&self) -> usize {
+            self.ply
+        }
+
+        fn last_action(&self) -> Option<Action> {
+            None
+        }
+
+        fn material(&self) -> Vec<usize> {
+            vec![]
+        }
+
+        fn validate(&self) -> Result<(), &'static str> {
+            Ok(())
+        }
+
+        fn state_key(&self) -> String {
+            self.ply.to_string()
+        }
+
+        fn ply_limit(&self) -> Option<usize> {
+            Some(4)
+        }
+
+        fn to_tensor(&self) -> (Vec<f32>, Vec<usize>) {
+            (vec![], vec![0])
+        }
+
+        fn action_space_size(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    fn rollouts_end_in_a_draw_at_the_ply_limit() {
+        let mut agent = Mcts::with_config(seeded(42));
+        agent.search(&Endless::default());
+        assert_eq!(agent.max_depth(), 4);
+        let root = &agent.nodes[0];
+        assert!((root.reward / root.visits - 0.5).abs() < 1e-9);
+        assert_eq!(agent.simulate(0, &mut vec![]), (GameResult::Draw, 4));
+        let game = play(Endless::default(), &[0, 1, 0]);
+        assert_eq!(game.result_with_limit(), None);
+        assert_eq!(play(game, &[1]).result_with_limit(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn good_moves_get_more_visits() {
+        // O holds the draw on an edge, while a corner lets X fork
+        let game = play(TicTacToe::default(), &[0, 4, 8]);
+        let mut agent = Mcts::with_config(seeded(42));
+        agent.search(&game);
+        assert!(agent.action_visits(5) > agent.action_visits(2));
+        assert!(agent.action_visits(5) > agent.action_visits(6));
+        assert!(agent.action_visits(4).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn rave_settles_on_a_good_move_sooner() {
+        // X makes an open three by playing next to their pair, at 1 or 4
+        let game = play(Connect4::default(), &[3, 3, 2, 2]);
+        let settled_at = |rave_bias| -> u32 {
+            (0..10)
+                .map(|seed| {
+                    let mut agent = Mcts::with_config(MctsConfig {
+                        iters: 512,
+                        rave_bias,
+                        record_checkpoints: true,
+                        ..seeded(seed)
+                    });
+                    agent.search(&game);
+                    agent
+                        .checkpoints()
+                        .iter()
+                        .rev()
+                        .take_while(|&&(_, action, _)| action == 1 || action == 4)
+                        .last()
+                        .map_or(512, |&(iteration, ..)| iteration)
+                })
+                .sum()
+        };
+        assert!(settled_at(Some(0.01)) < settled_at(None));
+    }
+
+    #[test]
+    fn continued_searches_grow_the_tree_like_longer_ones() {
+        let game = Connect4::default();
+        let config = MctsConfig {
+            iters: 500,
+            ..seeded(42)
+        };
+        let mut split = Mcts::with_config(config.clone());
+        split.search(&game);
+        assert!(split.continue_search(500).is_some());
+        let mut whole = Mcts::with_config(MctsConfig {
+            iters: 1_000,
+            ..config
+        });
+        whole.search(&game);
+        assert_eq!(split.tree_size(), whole.tree_size());
+        assert_eq!(split.tree_snapshot(), whole.tree_snapshot());
+        assert!((split.nodes[0].visits - 1_000.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn symmetric_openings_are_searched_once() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            reduce_symmetries: true,
+            ..seeded(42)
+        });
+        agent.search(&TicTacToe::default());
+        let mut tried = agent.analyze(&TicTacToe::default());
+        tried.sort_by_key(|&(action, ..)| action);
+        let actions: Vec<Action> = tried.iter().map(|&(action, ..)| action).collect();
+        assert_eq!(actions.len(), 3);
+        assert!(actions.contains(&4));
+
+        let mut full = Mcts::with_config(seeded(42));
+        full.search(&TicTacToe::default());
+        assert_eq!(full.nodes[0].children.len(), 9);
+    }
+
+    #[test]
+    fn terminal_leaves_reuse_their_cached_result() {
+        let mut agent = Mcts::with_config(seeded(5));
+        agent.search(&play(TicTacToe::default(), &[0, 3, 1, 4]));
+        let win = (0..agent.nodes.len())
+            .find(|&idx| agent.nodes[idx].parent == Some(0) && agent.nodes[idx].action == Some(2))
+            .expect("the winning move is expanded");
+        let expected = GameResult::Win(Player::X);
+        assert_eq!(agent.nodes[win].result, Some(expected));
+        let mut played = Vec::new();
+        assert_eq!(agent.simulate(win, &mut played), (expected, 0));
+        assert!(played.is_empty());
+    }
+
+    #[test]
+    fn each_iteration_expands_one_node() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 10,
+            ..seeded(1)
+        });
+        agent.search(&TicTacToe::default());
+        assert_eq!(agent.tree_size(), 11);
+        // The nine root moves are expanded first, then one reply
+        assert_eq!(agent.max_depth(), 2);
+        assert_eq!(agent.nodes[0].children.len(), 9);
+    }
+
+    #[test]
+    fn robust_max_searches_on_until_visits_and_value_agree() {
+        let game = play(Connect4::default(), &[3, 3, 2, 2]);
+        let mut refined = false;
+        for seed in 0..5 {
+            let mut agent = Mcts::with_config(MctsConfig {
+                iters: 30,
+                final_selection: FinalSelection::RobustMax {
+                    max_extra_iters: 10_000,
+                },
+                ..seeded(seed)
+            });
+            let action = agent.search(&game);
+            let rule = agent.config.backup_rule;
+            let children = &agent.nodes[0].children;
+            let by_visits = children
+                .iter()
+                .max_by(|a, b| agent.nodes[**a].visits.total_cmp(&agent.nodes[**b].visits));
+            let by_value = children.iter().max_by(|a, b| {
+                agent.nodes[**a]
+                    .value(rule)
+                    .total_cmp(&agent.nodes[**b].value(rule))
+            });
+            assert_eq!(by_visits, by_value, "seed {seed}");
+            assert_eq!(action, agent.nodes[*by_visits.unwrap()].action);
+            refined |= agent.iterations > 30;
+        }
+        assert!(refined, "the first 30 iterations always agreed");
+    }
+
+    #[test]
+    fn rebuilt_states_match_replaying_the_path() {
+        let game = play(Connect4::default(), &[3, 3]);
+        let search = |state_interval| {
+            let mut agent = Mcts::with_config(MctsConfig {
+                iters: 500,
+                state_interval,
+                ..seeded(4)
+            });
+            agent.search(&game);
+            agent
+        };
+        let sparse = search(3);
+        assert_eq!(sparse.tree_snapshot(), search(1).tree_snapshot());
+        for idx in 0..sparse.nodes.len() {
+            let node = &sparse.nodes[idx];
+            assert_eq!(node.state.is_some(), node.depth % 3 == 0);
+            let mut path = vec![];
+            let mut walk = idx;
+            while let Some(parent) = sparse.nodes[walk].parent {
+                path.push(sparse.nodes[walk].action.unwrap());
+                walk = parent;
+            }
+            path.reverse();
+            let replayed = play(game.clone(), &path);
+            assert_eq!(sparse.state(idx).state_key(), replayed.state_key());
+        }
+    }
+
+    #[test]
+    fn untried_root_actions_are_reported() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 2,
+            ..seeded(8)
+        });
+        agent.search(&TicTacToe::default());
+        let mut unexpanded = agent.unexpanded_root_actions();
+        let mut tried: Vec<Action> = agent.nodes[0]
+            .children
+            .iter()
+            .map(|&child| agent.nodes[child].action.unwrap())
+            .collect();
+        assert_eq!((unexpanded.len(), tried.len()), (7, 2));
+        unexpanded.append(&mut tried);
+        unexpanded.sort_unstable();
+        assert_eq!(unexpanded, (0..9).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn end_rewards_are_normalized_into_the_reward_range() {
+        let bounds = (100.0, 300.0);
+        let reward = |score| reward_for(GameResult::End(score), Player::X, bounds);
+        assert!((reward(200.0) - 0.5).abs() < 1e-12);
+        assert!((reward(150.0) - 0.25).abs() < 1e-12);
+        assert!(reward(50.0).abs() < f64::EPSILON);
+        assert!((reward(900.0) - 1.0).abs() < f64::EPSILON);
+        assert!((reward_for(GameResult::Win(Player::O), Player::O, bounds) - 1.0).abs() < 1e-12);
+        assert!(reward_for(GameResult::Win(Player::O), Player::X, bounds).abs() < 1e-12);
+        assert!((reward_for(GameResult::Draw, Player::X, bounds) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn greedy_rollouts_take_the_immediate_win() {
+        let game = play(Connect4::default(), &[0, 6, 1, 6, 2, 5]);
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 1,
+            epsilon_greedy_rollout: 0.0,
+            ..seeded(6)
+        });
+        agent.search(&game);
+        let mut played = vec![];
+        for _ in 0..20 {
+            assert_eq!(
+                agent.simulate(0, &mut played),
+                (GameResult::Win(Player::X), 1)
+            );
+        }
+    }
+
+    #[test]
+    fn children_get_their_minimum_visits_first() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 45,
+            min_visits: 5,
+            ..seeded(3)
+        });
+        agent.search(&TicTacToe::default());
+        let root = &agent.nodes[0];
+        assert_eq!(root.children.len(), 9);
+        for &child in &root.children {
+            assert!((agent.nodes[child].visits - 5.0).abs() < f64::EPSILON);
+        }
+    }
+
+    #[test]
+    fn pondered_trees_keep_their_visits_across_moves() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 300,
+            ..seeded(12)
+        });
+        let game = play(Connect4::default(), &[3]);
+        let visits_of = |agent: &Mcts<Connect4>, action| {
+            agent.nodes[0]
+                .children
+                .iter()
+                .map(|&idx| &agent.nodes[idx])
+                .find(|node| node.action == Some(action))
+                .map_or(0.0, |node| node.visits)
+        };
+        let action = agent.search(&game).unwrap();
+        let searched = visits_of(&agent, action);
+        assert!(agent.advance_root(action));
+        assert!((agent.nodes[0].visits - searched).abs() < f64::EPSILON);
+
+        agent.ponder(&AtomicBool::new(true));
+        assert!((agent.nodes[0].visits - searched).abs() < f64::EPSILON);
+        agent.ponder(&AtomicBool::new(false));
+        assert!((agent.nodes[0].visits - searched - 300.0).abs() < f64::EPSILON);
+
+        let reply = agent.expected_reply().unwrap();
+        let pondered = visits_of(&agent, reply);
+        assert!(pondered > 0.0);
+        assert!(agent.advance_root(reply));
+        assert!((agent.nodes[0].visits - pondered).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn policy_targets_share_the_root_visits() {
+        let game = play(Connect4::default(), &[3, 3, 3, 3, 3, 3]);
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 500,
+            ..seeded(2)
+        });
+        agent.search(&game);
+        let policy = agent.policy_target(game.action_space_size());
+        assert_eq!(policy.len(), 7);
+        assert!((policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(policy[3].abs() < f32::EPSILON);
+
+        let most_visited = agent.nodes[0]
+            .children
+            .iter()
+            .map(|&idx| &agent.nodes[idx])
+            .max_by(|a, b| a.visits.total_cmp(&b.visits))
+            .and_then(|node| node.action);
+        let argmax = (0..policy.len()).max_by(|&a, &b| policy[a].total_cmp(&policy[b]));
+        assert_eq!(argmax, most_visited);
+    }
+
+    #[test]
+    fn parallel_searches_are_reproducible() {
+        let game = play(Connect4::default(), &[3, 3]);
+        let search = || {
+            let mut agent = Mcts::with_config(MctsConfig {
+                iters: 400,
+                threads: 4,
+                ..seeded(21)
+            });
+            let action = agent.search_parallel(&game);
+            (action, agent.root_visits(), agent.tree_snapshot())
+        };
+        let (action, visits, snapshot) = search();
+        assert!(action.is_some_and(|action| game.allowed_actions().contains(&action)));
+        assert_eq!(search(), (action, visits, snapshot));
+    }
+
+    /// A puzzle ending at once with reward 1 after action 0, or with reward 2 after
+    /// action 1 and three more forced moves
+    #[derive(Clone, Debug, Default)]
+    struct Detour {
+        moves: Vec<Action>,
+    }
+
+    impl Game for Detour {
+        fn print_instructions(&self) {}
+
+        fn num_players(&self) -> usize {
+            1
+        }
+
+        fn next_player(&self) -> Player {
+            Player::X
+        }
+
+        fn result(&self) -> Option<GameResult> {
+            match self.moves[..] {
+                [0] => Some(GameResult::End(1.0)),
+                [1, _, _, _] => Some(GameResult::End(2.0)),
+                _ => None,
+            }
+        }
+
+        fn reward_range(&self) -> (f64, f64) {
+            (0.0, 2.0)
+        }
+
+        fn allowed_actions(&self) -> Vec<Action> {
+            match self.result() {
+                Some(_) => vec![],
+                None if self.moves.is_empty() => vec![0, 1],
+                None => vec![0],
+            }
+        }
+
+        fn current_player(&self) -> Player {
+            Player::X
+        }
+
+        fn step(&mut self, action: Action) -> Result<(), &'static str> {
+            if !self.allowed_actions().contains(&action) {
+                return Err("Invalid move");
+            }
+            self.moves.push(action);
+            Ok(())
+        }
+
+        fn ply(&self) -> usize {
+            self.moves.len()
+        }
+
+        fn last_action(&self) -> Option<Action> {
+            self.moves.last().copied()
+        }
+
+        fn material(&self) -> Vec<usize> {
+            vec![]
+        }
+
+        fn validate(&self) -> Result<(), &'static str> {
+            Ok(())
+        }
+
+        fn state_key(&self) -> String {
+            format!("{:?}", self.moves)
+        }
+
+        fn to_tensor(&self) -> (Vec<f32>, Vec<usize>) {
+            (vec![], vec![0])
+        }
+
+        fn action_space_size(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    fn reward_per_move_prefers_the_quick_reward() {
+        let search = |reward_per_move| {
+            Mcts::with_config(MctsConfig {
+                iters: 200,
+                reward_per_move,
+                ..seeded(1)
+            })
+            .search(&Detour::default())
+        };
+        assert_eq!(search(false), Some(1));
+        assert_eq!(search(true), Some(0));
+    }
+
+    #[test]
+    fn nodes_are_credited_to_the_player_who_moved() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 100,
+            ..seeded(13)
+        });
+        agent.search(&ExtraTurn::default());
+        assert_eq!(agent.max_depth(), 3);
+        for node in &agent.nodes[1..] {
+            let mover = if node.depth < 3 { Player::X } else { Player::O };
+            assert_eq!(node.actor, mover, "depth {}", node.depth);
+            // X's second move decides the game, so it is won or lost outright for X
+            if node.depth == 2 {
+                let won = f64::from(u8::from(node.action == Some(1)));
+                assert!((node.reward - won * node.visits).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn checkpoints_are_recorded_at_powers_of_two_and_the_end() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 100,
+            record_checkpoints: true,
+            ..seeded(10)
+        });
+        let action = agent.search(&play(Connect4::default(), &[3, 3]));
+        let iterations: Vec<u32> = agent.checkpoints().iter().map(|c| c.0).collect();
+        assert_eq!(iterations, [1, 2, 4, 8, 16, 32, 64, 100]);
+        let &(_, last_action, last_value) = agent.checkpoints().last().unwrap();
+        assert_eq!(Some(last_action), action);
+        assert_eq!(Some(last_value), agent.root_value());
+    }
+
+    #[test]
+    fn expansion_order_picks_the_first_child() {
+        let expanded = |expansion_order| {
+            let mut agent = Mcts::with_config(MctsConfig {
+                iters: 1,
+                expansion_order,
+                ..seeded(0)
+            });
+            agent.search(&Connect4::default());
+            let children = &agent.nodes[0].children;
+            assert_eq!(children.len(), 1);
+            agent.nodes[children[0]].action
+        };
+        assert_eq!(expanded(ExpansionOrder::First), Some(0));
+        assert_eq!(expanded(ExpansionOrder::Last), Some(6));
+        assert_eq!("first".parse(), Ok(ExpansionOrder::First));
+        assert!("middle".parse::<ExpansionOrder>().is_err());
+    }
+
+    #[test]
+    fn max_backups_keep_the_best_rollout() {
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 300,
+            backup_rule: BackupRule::Max,
+            ..seeded(3)
+        });
+        agent.search(&Tetris::with_seed(3));
+        for node in agent.nodes.iter().filter(|node| node.visits > 0.0) {
+            let (max, average) = (node.value(BackupRule::Max), node.value(BackupRule::Average));
+            assert!(max >= average - 1e-12 && max <= 1.0);
+        }
+        let chosen = &agent.nodes[agent.chosen_child().unwrap()];
+        assert!(agent.root_value().unwrap() > chosen.value(BackupRule::Average));
+    }
+
+    #[test]
+    fn seeded_searches_are_reproducible() {
+        let game = play(Connect4::default(), &[3, 3, 2]);
+        let snapshot = |seed| {
+            let mut agent = Mcts::with_config(seeded(seed));
+            agent.search(&game);
+            agent.tree_snapshot()
+        };
+        assert_eq!(snapshot(42), snapshot(42));
+        assert_ne!(snapshot(42), snapshot(43));
+
+        let mut rng = fastrand::Rng::with_seed(1);
+        let full = play(Connect4::default(), &[3, 3, 3, 3, 3, 3]);
+        for _ in 0..50 {
+            assert_ne!(full.rollout_action(&mut rng), 3);
+        }
+    }
+
+    #[test]
+    fn root_noise_reshuffles_visits_but_keeps_the_forced_block() {
+        let dirichlet = Some(RootNoise {
+            alpha: 0.3,
+            epsilon: 0.25,
+        });
+        let search = |game: &Connect4, root_noise| {
+            let mut agent = Mcts::with_config(MctsConfig {
+                iters: 300,
+                root_noise,
+                ..seeded(17)
+            });
+            agent.search(game);
+            agent
+        };
+        let opening = Connect4::default();
+        let quiet = search(&opening, None);
+        let noisy = search(&opening, dirichlet);
+        assert!(quiet.noise.is_empty());
+        assert_eq!(noisy.noise.len(), 7);
+        assert!((noisy.noise.values().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_ne!(noisy.root_visits(), quiet.root_visits());
+
+        // X threatens to complete the bottom row at 3, so O has to block there
+        let threat = play(Connect4::default(), &[0, 6, 1, 6, 2]);
+        assert_eq!(search(&threat, dirichlet).best_action(), Some(3));
+    }
+
+    #[test]
+    fn selection_stops_at_the_depth_limit() {
+        let search = |max_select_depth| {
+            let mut agent = Mcts::with_config(MctsConfig {
+                iters: 500,
+                max_select_depth,
+                ..seeded(14)
+            });
+            agent.search(&Connect4::default());
+            agent.max_depth()
+        };
+        assert_eq!(search(Some(2)), 2);
+        assert_eq!(search(Some(0)), 1);
+        assert!(search(None) > 2);
+    }
+
+    #[test]
+    fn repeated_searches_reuse_buffers_without_changing_the_tree() {
+        let game = play(Connect4::default(), &[3, 3, 2]);
+        let mut agent = Mcts::with_config(seeded(9));
+        agent.search(&Connect4::default());
+        assert_eq!(agent.reused_buffers(), 0);
+        agent.rng = fastrand::Rng::with_seed(9);
+        agent.search(&game);
+        assert!(agent.reused_buffers() > 0);
+
+        let mut fresh = Mcts::with_config(seeded(9));
+        fresh.search(&game);
+        assert_eq!(agent.tree_snapshot(), fresh.tree_snapshot());
+    }
+
+    #[test]
+    fn expected_reply_is_the_forced_response() {
+        // O must block 2, which threatens 2-4-6, so X is expected to block 6
+        let game = play(TicTacToe::default(), &[0, 4, 1]);
+        let mut agent = Mcts::with_config(seeded(3));
+        assert_eq!(agent.search(&game), Some(2));
+        assert_eq!(agent.expected_reply(), Some(6));
+    }
+
+    #[test]
+    fn ignoring_a_win_is_a_large_value_gap() {
+        // X wins at 2, while 8 lets O win at 5
+        let game = play(TicTacToe::default(), &[0, 3, 1, 4]);
+        let mut coach = Mcts::with_config(MctsConfig {
+            opening_book: true,
+            tactical_scan: true,
+            ..seeded(4)
+        });
+        let gap = coach.value_gap(&game, 8).unwrap();
+        assert!(gap > 0.5, "gap {gap}");
+        assert!(coach.value_gap(&game, 2).unwrap() < f64::EPSILON);
+        assert!(coach.config.tactical_scan && coach.config.opening_book);
+
+        let forced = play(TicTacToe::default(), &[0, 1, 2, 4, 3, 6, 5, 8]);
+        assert_eq!(coach.value_gap(&forced, 7), Some(0.0));
+    }
+
+    #[test]
+    fn node_budgets_cap_the_tree() {
+        let game = play(Connect4::default(), &[3, 3]);
+        let mut agent = Mcts::with_config(MctsConfig {
+            node_budget: Some(50),
+            ..seeded(8)
+        });
+        let action = agent.search(&game).unwrap();
+        assert!(game.allowed_actions().contains(&action));
+        assert_eq!(agent.tree_size(), 50);
+        assert!(agent.iterations <= 50);
+    }
+
+    #[test]
+    fn analyze_puts_the_winning_move_first() {
+        let game = play(TicTacToe::default(), &[0, 3, 1, 4]);
+        let analysis = Mcts::with_config(seeded(6)).analyze(&game);
+        assert_eq!(analysis.len(), 5);
+        // The win proves the root at once, so other moves may tie on a lucky rollout
+        assert_eq!(analysis[0].0, 2);
+        assert!(analysis.iter().all(|&(_, value, _)| value <= analysis[0].1));
+        assert!(analysis[1..].windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    }
+
+    #[test]
+    fn shared_seeding_still_searches_every_iteration() {
+        let game = play(Connect4::default(), &[3, 3]);
+        let mut agent = Mcts::with_config(MctsConfig {
+            iters: 400,
+            threads: 4,
+            worker_seeding: WorkerSeeding::Shared,
+            ..seeded(21)
+        });
+        let action = agent.search_parallel(&game).unwrap();
+        assert!(game.allowed_actions().contains(&action));
+        assert!((agent.nodes[0].visits - 400.0).abs() < f64::EPSILON);
+        let child_visits: f64 = agent.root_visits().iter().map(|&(_, visits)| visits).sum();
+        assert!((child_visits - 400.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn exploration_decays_over_the_iteration_budget() {
+        let scales = |decay| {
+            let mut agent: Mcts<TicTacToe> = Mcts::with_config(MctsConfig {
+                iters: 100,
+                exploration_schedule: Some(ExplorationSchedule {
+                    start: 4.0,
+                    end: 1.0,
+                    decay,
+                }),
+                ..seeded(1)
+            });
+            [0, 50, 100, 200]
+                .map(|iterations| {
+                    agent.iterations = iterations;
+                    agent.exploration()
+                })
+                .to_vec()
+        };
+        assert_eq!(scales(Decay::Linear), [4.0, 2.5, 1.0, 1.0]);
+        assert_eq!(scales(Decay::Exponential), [4.0, 2.0, 1.0, 1.0]);
+        assert!(
+            (Mcts::<TicTacToe>::with_config(seeded(1)).exploration() - 1.0).abs() < f64::EPSILON
+        );
+    }
+
+    #[test]
+    fn forced_moves_skip_the_search() {
+        let mut game = Connect4::new(2, 2, 2);
+        game.step(0).unwrap();
+        game.step(0).unwrap();
+        let mut agent = Mcts::with_config(seeded(42));
+        assert_eq!(agent.search(&game), Some(1));
+        assert_eq!(agent.tree_size(), 1);
+    }
+
+    #[test]
+    fn search_after_finds_the_forced_reply() {
+        // X taking 1 threatens 0-1-2, so O must reply at 2
+        let game = play(TicTacToe::default(), &[0, 4]);
+        let mut agent = Mcts::with_config(seeded(12));
+        let (reply, value) = agent.search_after(&game, 1).unwrap();
+        assert_eq!(reply, Some(2));
+        assert!(value.is_some());
+        assert_eq!(agent.search_after(&game, 4), Err("Illegal first move"));
+    }
 }
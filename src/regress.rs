@@ -0,0 +1,104 @@
+use crate::game::{Action, Game, connect4::Connect4, tictactoe::TicTacToe};
+use crate::mcts::{Mcts, MctsConfig};
+
+const REGRESS_ITERS: u32 = 2_000;
+const REGRESS_SEED: u64 = 42;
+
+/// Golden Connect 4 positions, as the moves leading to them, and the agent's choice
+const CONNECT4_GOLDEN: [(&[Action], Action); 5] = [
+    (&[], 3),
+    (&[3], 2),
+    (&[3, 3, 2, 4], 2),
+    (&[3, 3, 2, 4, 2, 2, 4, 1], 3),
+    (&[0, 6, 1, 6, 2], 3),
+];
+
+/// Golden Tic-Tac-Toe positions, as the moves leading to them, and the agent's choice
+const TICTACTOE_GOLDEN: [(&[Action], Action); 4] =
+    [(&[4], 6), (&[0, 4, 8], 5), (&[0, 1, 4], 8), (&[4, 0, 2], 6)];
+
+/// Digests of the whole search tree from the initial Connect 4 and Tic-Tac-Toe positions
+const CONNECT4_TREE_DIGEST: u64 = 0xa5dc_0219_6b2a_2777;
+const TICTACTOE_TREE_DIGEST: u64 = 0xfb40_d08b_ec0c_5764;
+
+/// Search every golden position with a fixed seed and iteration budget,
+/// returning a description of each choice that differs from the golden one
+/// and of each search tree that differs from the golden tree
+pub fn check_golden() -> Vec<String> {
+    let mut mismatches = check_positions(&Connect4::default(), &CONNECT4_GOLDEN);
+    mismatches.extend(check_positions(&TicTacToe::default(), &TICTACTOE_GOLDEN));
+    mismatches.extend(check_tree(&Connect4::default(), CONNECT4_TREE_DIGEST));
+    mismatches.extend(check_tree(&TicTacToe::default(), TICTACTOE_TREE_DIGEST));
+    mismatches
+}
+
+fn agent<G: Game>() -> Mcts<G> {
+    Mcts::with_config(MctsConfig {
+        iters: REGRESS_ITERS,
+        seed: Some(REGRESS_SEED),
+        opening_book: false,
+        ..MctsConfig::default()
+    })
+}
+
+fn check_positions<G: Game + std::fmt::Display>(
+    start: &G,
+    golden: &[(&[Action], Action)],
+) -> Vec<String> {
+    golden
+        .iter()
+        .filter_map(|&(moves, expected)| {
+            let mut game = start.clone();
+            for &action in moves {
+                game.step(action).unwrap();
+            }
+            let chosen = agent().search(&game);
+            (chosen != Some(expected))
+                .then(|| format!("after {moves:?}: expected {expected}, got {chosen:?}\n{game}"))
+        })
+        .collect()
+}
+
+/// Search `start` and compare the digest of the tree snapshot with `golden`,
+/// catching any change of behavior even when the chosen move stays the same
+fn check_tree<G: Game + std::fmt::Display>(start: &G, golden: u64) -> Option<String> {
+    let mut agent = agent();
+    agent.search(start);
+    let digest = fnv1a(agent.tree_snapshot().as_bytes());
+    (digest != golden).then(|| format!("tree digest {digest:#x}, expected {golden:#x}\n{start}"))
+}
+
+/// 64-bit FNV-1a hash, stable across platforms and Rust versions unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver;
+
+    #[test]
+    fn golden_tictactoe_moves_are_optimal() {
+        for &(moves, expected) in &TICTACTOE_GOLDEN {
+            let mut game = TicTacToe::default();
+            for &action in moves {
+                game.step(action).unwrap();
+            }
+            let mut next = game.clone();
+            next.step(expected).unwrap();
+            assert_eq!(
+                -solver::solve(&next),
+                solver::solve(&game),
+                "after {moves:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn golden_positions_match() {
+        assert_eq!(check_golden(), Vec::<String>::new());
+    }
+}
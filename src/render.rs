@@ -0,0 +1,151 @@
+use raylib::color::Color;
+use raylib::prelude::*;
+
+use crate::game::Player;
+
+pub const HALF_LINEWIDTH: i32 = 1;
+pub const SQUARE_SIZE: i32 = 32;
+
+/// Frames during which a newly placed piece blinks
+const BLINK_FRAMES: usize = 30;
+
+/// Pixel position of the top-left corner of the cell at (row, col)
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub fn cell_position(row: usize, col: usize) -> (i32, i32) {
+    (col as i32 * SQUARE_SIZE, row as i32 * SQUARE_SIZE)
+}
+
+/// Draw a filled square with its outline, with the top-left corner at (x, y)
+pub fn draw_cell(d: &mut impl RaylibDraw, x: i32, y: i32, color: Color, line_color: Color) {
+    d.draw_rectangle(
+        x + HALF_LINEWIDTH,
+        y + HALF_LINEWIDTH,
+        SQUARE_SIZE - 2 * HALF_LINEWIDTH,
+        SQUARE_SIZE - 2 * HALF_LINEWIDTH,
+        color,
+    );
+    d.draw_rectangle(
+        x - HALF_LINEWIDTH,
+        y - HALF_LINEWIDTH,
+        SQUARE_SIZE,
+        2 * HALF_LINEWIDTH,
+        line_color,
+    );
+    d.draw_rectangle(
+        x - HALF_LINEWIDTH,
+        y + SQUARE_SIZE - HALF_LINEWIDTH,
+        SQUARE_SIZE,
+        2 * HALF_LINEWIDTH,
+        line_color,
+    );
+    d.draw_rectangle(
+        x - HALF_LINEWIDTH,
+        y - HALF_LINEWIDTH,
+        2 * HALF_LINEWIDTH,
+        SQUARE_SIZE,
+        line_color,
+    );
+    d.draw_rectangle(
+        x + SQUARE_SIZE - HALF_LINEWIDTH,
+        y - HALF_LINEWIDTH,
+        2 * HALF_LINEWIDTH,
+        SQUARE_SIZE,
+        line_color,
+    );
+}
+
+/// Games that can be drawn as a grid of cells owned by players
+pub trait Board {
+    fn dimensions(&self) -> (usize, usize);
+    fn owner(&self, row: usize, col: usize) -> Option<Player>;
+}
+
+/// Render client for two-player board games
+pub struct BoardClient {
+    rows: usize,
+    cols: usize,
+    cells: Vec<Option<Player>>,
+    rl: RaylibHandle,
+    thread: RaylibThread,
+}
+
+impl BoardClient {
+    pub fn new(board: &impl Board, title: &str) -> Self {
+        let (rows, cols) = board.dimensions();
+        let (width, height) = cell_position(rows, cols);
+        let (mut rl, thread) = raylib::init().size(width, height).title(title).build();
+        rl.set_target_fps(60);
+
+        BoardClient {
+            rows,
+            cols,
+            cells: vec![None; rows * cols],
+            rl,
+            thread,
+        }
+    }
+
+    /// Draw the board, blinking the pieces placed since the last call
+    pub fn render(&mut self, board: &impl Board) {
+        let cells: Vec<Option<Player>> = (0..self.rows)
+            .flat_map(|row| (0..self.cols).map(move |col| (row, col)))
+            .map(|(row, col)| board.owner(row, col))
+            .collect();
+        let frames = if cells == self.cells { 1 } else { BLINK_FRAMES };
+
+        for frame in 0..frames {
+            if self.rl.window_should_close() {
+                return;
+            }
+            let blink = frames > 1 && (frame / 5) % 2 == 0;
+
+            let mut d = self.rl.begin_drawing(&self.thread);
+            d.clear_background(Color::BLACK);
+            for (i, cell) in cells.iter().enumerate() {
+                let color = match cell {
+                    _ if blink && *cell != self.cells[i] => Color::WHITE,
+                    Some(Player::X) => Color::new(230, 41, 55, 255),
+                    Some(Player::O) => Color::new(253, 249, 0, 255),
+                    None => Color::BLACK,
+                };
+                let (x, y) = cell_position(i / self.cols, i % self.cols);
+                draw_cell(&mut d, x, y, color, Color::new(80, 80, 80, 255));
+            }
+        }
+        self.cells = cells;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Game;
+    use crate::game::connect4::Connect4;
+    use crate::game::tictactoe::TicTacToe;
+
+    #[test]
+    fn cells_are_laid_out_in_squares() {
+        assert_eq!(cell_position(0, 0), (0, 0));
+        assert_eq!(cell_position(2, 5), (5 * SQUARE_SIZE, 2 * SQUARE_SIZE));
+        // The window size is the position of the cell past the last row and column
+        assert_eq!(cell_position(6, 7), (224, 192));
+    }
+
+    #[test]
+    fn boards_report_owners_top_down() {
+        let mut connect4 = Connect4::default();
+        connect4.step(3).unwrap();
+        connect4.step(3).unwrap();
+        let (rows, cols) = connect4.dimensions();
+        assert_eq!((rows, cols), (6, 7));
+        assert_eq!(connect4.owner(rows - 1, 3), Some(Player::X));
+        assert_eq!(connect4.owner(rows - 2, 3), Some(Player::O));
+        assert_eq!(connect4.owner(0, 3), None);
+
+        let mut tictactoe = TicTacToe::default();
+        tictactoe.step(5).unwrap();
+        assert_eq!(tictactoe.dimensions(), (3, 3));
+        assert_eq!(tictactoe.owner(1, 2), Some(Player::X));
+        assert_eq!(tictactoe.owner(2, 1), None);
+    }
+}
@@ -0,0 +1,142 @@
+use std::io::{self, BufRead, Write};
+
+use serde_json::{Value, json};
+
+use crate::game::{Action, GameDyn, GameResult};
+
+/// A line read from the external agent
+enum Command {
+    Step(Action),
+    /// Start a new episode from the initial position
+    Reset,
+}
+
+/// Play `game` for an external agent, one JSON object per line.
+/// Writes the initial observation, then reads `{"action": N}` commands from `input` and answers
+/// each with the observation after the step and the `reward` it earned,
+/// or with `{"error": ...}` if the command failed.
+/// `{"reset": true}` goes back to the initial position and answers with its observation.
+pub fn serve(
+    mut game: Box<dyn GameDyn>,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<()> {
+    let initial = game.clone();
+    writeln!(output, "{}", observation(game.as_ref()))?;
+    output.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match parse_command(&line) {
+            Ok(Command::Reset) => {
+                game = initial.clone();
+                observation(game.as_ref())
+            }
+            Ok(Command::Step(action)) => match game.step_returning_reward(action) {
+                Ok(reward) => {
+                    let mut observation = observation(game.as_ref());
+                    observation["reward"] = json!(reward);
+                    observation
+                }
+                Err(e) => json!({ "error": e }),
+            },
+            Err(e) => json!({ "error": e }),
+        };
+        writeln!(output, "{reply}")?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn parse_command(line: &str) -> Result<Command, &'static str> {
+    let command: Value = serde_json::from_str(line).map_err(|_| "Invalid JSON")?;
+    if command.get("reset").and_then(Value::as_bool) == Some(true) {
+        return Ok(Command::Reset);
+    }
+    command
+        .get("action")
+        .and_then(Value::as_u64)
+        .and_then(|action| Action::try_from(action).ok())
+        .map(Command::Step)
+        .ok_or("Expected {\"action\": N} or {\"reset\": true}")
+}
+
+/// The board tensor, legal actions, player to move, position key and result of the game
+fn observation(game: &dyn GameDyn) -> Value {
+    let (board, shape) = game.to_tensor();
+    json!({
+        "board": board,
+        "shape": shape,
+        "legal": game.allowed_actions(),
+        "mask": game.legal_action_mask(),
+        "player": game.current_player().to_string(),
+        "key": game.state_key(),
+        "result": game.result_with_limit().map(|result| match result {
+            GameResult::Win(player) => json!({ "win": player.to_string() }),
+            GameResult::Draw => json!("draw"),
+            GameResult::End(reward) => json!({ "end": reward }),
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::serve;
+    use crate::game::tictactoe::TicTacToe;
+
+    fn replies(input: &str) -> Vec<Value> {
+        let mut output = vec![];
+        serve(
+            Box::new(TicTacToe::default()),
+            input.as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn reset_returns_to_the_initial_position() {
+        let replies = replies("{\"action\": 4}\n{\"action\": 4}\n{\"reset\": true}\n");
+        assert_eq!(replies.len(), 4);
+        assert_eq!(replies[1]["key"], "....X....");
+        assert!(replies[2]["error"].is_string());
+        assert_eq!(replies[3], replies[0]);
+    }
+
+    #[test]
+    fn scripted_games_report_each_observation() {
+        let script = ["0", "3", "1", "4", "2"]
+            .map(|action| format!("{{\"action\": {action}}}\n"))
+            .concat();
+        let replies = replies(&format!("{script}not json\n"));
+        assert_eq!(replies.len(), 7);
+
+        let initial = &replies[0];
+        assert_eq!(initial["shape"], serde_json::json!([3, 3, 3]));
+        assert_eq!(initial["board"].as_array().unwrap().len(), 27);
+        assert_eq!(initial["legal"].as_array().unwrap().len(), 9);
+        assert_eq!(initial["player"], "X");
+        assert!(initial["result"].is_null());
+        assert!(initial.get("reward").is_none());
+
+        assert_eq!(replies[1]["key"], "X........");
+        assert_eq!(replies[1]["player"], "O");
+        assert_eq!(replies[1]["mask"][0], false);
+        assert_eq!(replies[1]["reward"], 0.0);
+
+        let won = &replies[5];
+        assert_eq!(won["key"], "XXXOO....");
+        assert_eq!(won["result"]["win"], "X");
+        assert!(won["legal"].as_array().unwrap().is_empty());
+        assert_eq!(replies[6]["error"], "Invalid JSON");
+    }
+}
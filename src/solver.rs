@@ -0,0 +1,111 @@
+use crate::game::{Action, Game, GameResult};
+
+/// Exact value of the game for the player to move, with perfect play from both sides:
+/// 1 for a win, 0 for a draw and -1 for a loss.
+/// Searches the whole game tree, so only suits small games.
+pub fn solve<G: Game>(game: &G) -> i32 {
+    match game.result_with_limit() {
+        Some(GameResult::Win(player)) => {
+            if player == game.current_player() {
+                1
+            } else {
+                -1
+            }
+        }
+        Some(GameResult::Draw | GameResult::End(_)) => 0,
+        None => game
+            .successors()
+            .iter()
+            .map(|(_, next)| value_of(game, next))
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+/// A move keeping the best achievable result, or `None` if the game is over
+pub fn perfect_move<G: Game>(game: &G) -> Option<Action> {
+    if game.result_with_limit().is_some() {
+        return None;
+    }
+    game.successors()
+        .into_iter()
+        .max_by_key(|(_, next)| value_of(game, next))
+        .map(|(action, _)| action)
+}
+
+/// Value of the successor `next` of `game` for the player who moved, who may move again
+fn value_of<G: Game>(game: &G, next: &G) -> i32 {
+    game.turn_parity(next) * solve(next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{perfect_move, solve};
+    use crate::game::{Game, GameResult, Player, tictactoe::TicTacToe};
+
+    fn play(moves: &[usize]) -> TicTacToe {
+        let mut game = TicTacToe::default();
+        for &action in moves {
+            game.step(action).unwrap();
+        }
+        game
+    }
+
+    /// Play every line of the opponent against perfect play by `perfect`,
+    /// failing if any of them wins
+    fn never_loses(game: &TicTacToe, perfect: Player) {
+        match game.result() {
+            Some(result) => assert_ne!(result, GameResult::Win(perfect.opponent()), "\n{game}"),
+            None if game.current_player() == perfect => {
+                let mut next = game.clone();
+                next.step(perfect_move(game).unwrap()).unwrap();
+                never_loses(&next, perfect);
+            }
+            None => {
+                for (_, next) in game.successors() {
+                    never_loses(&next, perfect);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tictactoe_is_a_draw() {
+        assert_eq!(solve(&TicTacToe::default()), 0);
+    }
+
+    #[test]
+    fn perfect_play_wins_when_it_can() {
+        // X threatens 5 and 6 at once, so O to move loses
+        let game = play(&[0, 1, 4, 8, 3]);
+        assert_eq!(solve(&game), -1);
+        let game = play(&[0, 1, 4, 8]);
+        assert_eq!(solve(&game), 1);
+        let win = perfect_move(&game).unwrap();
+        assert_eq!(solve(&play(&[0, 1, 4, 8, win])), -1);
+    }
+
+    #[test]
+    fn perfect_play_blocks() {
+        // X threatens to complete 0-1-2, and only taking 2 holds the draw
+        let game = play(&[0, 4, 1]);
+        assert_eq!(solve(&game), 0);
+        for action in [3, 5, 6, 7, 8] {
+            assert_eq!(solve(&play(&[0, 4, 1, action])), 1, "O plays {action}");
+        }
+        assert_eq!(perfect_move(&game), Some(2));
+    }
+
+    #[test]
+    fn perfect_play_never_loses() {
+        never_loses(&TicTacToe::default(), Player::X);
+        never_loses(&TicTacToe::default(), Player::O);
+    }
+
+    #[test]
+    fn finished_games_have_no_move() {
+        let game = play(&[0, 3, 1, 4, 2]);
+        assert_eq!(solve(&game), -1);
+        assert_eq!(perfect_move(&game), None);
+    }
+}